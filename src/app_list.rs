@@ -0,0 +1,103 @@
+//! A full list of Steam app names, downloaded once so games which aren't installed
+//! can be named without looking each one up
+
+use anyhow::{Context, Result};
+use directories::ProjectDirs;
+use reqwest::blocking::{Client, Response};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Steam Web API endpoint which lists every app on Steam
+const APP_LIST_URL: &str = "https://api.steampowered.com/ISteamApps/GetAppList/v2/";
+
+/// Longest to wait for the app list to download, which is several megabytes
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Where the app list is kept, such as `~/.cache/lnshot/app_list.tsv` on Linux
+pub fn default_path() -> Option<PathBuf> {
+    ProjectDirs::from_path(PathBuf::from("lnshot"))
+        .map(|project_dirs| project_dirs.cache_dir().join("app_list.tsv"))
+}
+
+/// Downloads the list of every app on Steam, and saves it to the given path
+/// as lines of tab-separated app IDs and names.
+///
+/// Returns the number of apps in the list.
+pub fn download(path: &Path) -> Result<usize> {
+    let response = Client::builder()
+        .timeout(TIMEOUT)
+        .build()?
+        .get(APP_LIST_URL)
+        .send()
+        .and_then(Response::error_for_status)
+        .with_context(|| "Failed to download the app list")?
+        .json::<serde_json::Value>()
+        .with_context(|| "Failed to parse the app list")?;
+
+    let apps = response
+        .get("applist")
+        .and_then(|applist| applist.get("apps"))
+        .and_then(serde_json::Value::as_array)
+        .with_context(|| "The app list didn't contain any apps")?;
+
+    let mut names = BTreeMap::new();
+
+    for app in apps {
+        let (Some(appid), Some(name)) = (
+            app.get("appid").and_then(serde_json::Value::as_u64),
+            app.get("name").and_then(serde_json::Value::as_str),
+        ) else {
+            continue;
+        };
+
+        // Tabs and line breaks would break up the list, and don't belong in folder names anyway
+        let name = name.split_whitespace().collect::<Vec<_>>().join(" ");
+
+        if !name.is_empty() {
+            names.insert(appid, name);
+        }
+    }
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create cache folder {:?}", parent))?;
+    }
+
+    let contents = names
+        .iter()
+        .map(|(appid, name)| format!("{}\t{}\n", appid, name))
+        .collect::<String>();
+
+    std::fs::write(path, contents)
+        .with_context(|| format!("Failed to write app list {:?}", path))?;
+
+    Ok(names.len())
+}
+
+/// Finds names for the given app IDs in the app list saved at the given path.
+/// Apps which aren't in the list are left out, and if the list hasn't been downloaded, nothing is found.
+pub fn lookup(path: &Path, appids: &HashSet<u64>) -> Result<HashMap<u64, String>> {
+    let mut found_names = HashMap::new();
+
+    if appids.is_empty() || !path.is_file() {
+        return Ok(found_names);
+    }
+
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read app list {:?}", path))?;
+
+    for line in contents.lines() {
+        let Some((appid_str, name)) = line.split_once('\t') else {
+            continue;
+        };
+
+        if let Ok(appid) = appid_str.parse() {
+            if appids.contains(&appid) {
+                found_names.insert(appid, name.to_string());
+            }
+        }
+    }
+
+    Ok(found_names)
+}
@@ -0,0 +1,220 @@
+//! Minimal reader for Steam's binary `appcache/appinfo.vdf` file,
+//! which holds metadata for every app the Steam client knows about.
+
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Magic number for appinfo files written before string tables were introduced
+const MAGIC_V27: u32 = 0x07564427;
+
+/// Magic number for appinfo files which include a binary SHA-1 of each app's data
+const MAGIC_V28: u32 = 0x07564428;
+
+/// Magic number for appinfo files which store key names in a shared string table
+const MAGIC_V29: u32 = 0x07564429;
+
+/// A value in Steam's binary key-value format
+#[derive(Debug)]
+enum Value {
+    Section(HashMap<String, Value>),
+    String(String),
+    Integer(u64),
+    Other,
+}
+
+impl Value {
+    /// Looks up a nested value by its path of keys
+    fn lookup(&self, keys: &[&str]) -> Option<&Value> {
+        keys.iter().try_fold(self, |value, key| match value {
+            Value::Section(section) => section.get(*key),
+            _ => None,
+        })
+    }
+
+    /// Reads the value as a number, whether it was stored as a number or a string
+    fn as_u32(&self) -> Option<u32> {
+        match self {
+            Value::String(string) => string.parse().ok(),
+            Value::Integer(integer) => u32::try_from(*integer).ok(),
+            _ => None,
+        }
+    }
+}
+
+/// Reads through the bytes of an appinfo file
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+    string_table: Option<Vec<String>>,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, length: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .data
+            .get(self.position..self.position + length)
+            .with_context(|| "Unexpected end of appinfo data")?;
+
+        self.position += length;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into()?))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into()?))
+    }
+
+    fn c_string(&mut self) -> Result<String> {
+        let length = self
+            .data
+            .get(self.position..)
+            .with_context(|| "Unexpected end of appinfo data")?
+            .iter()
+            .position(|byte| *byte == 0)
+            .with_context(|| "Unterminated string in appinfo data")?;
+
+        let string = String::from_utf8_lossy(self.bytes(length)?).into_owned();
+        self.position += 1;
+
+        Ok(string)
+    }
+
+    fn key(&mut self) -> Result<String> {
+        match &self.string_table {
+            Some(_) => {
+                let index = self.u32()? as usize;
+
+                self.string_table
+                    .as_ref()
+                    .and_then(|string_table| string_table.get(index))
+                    .cloned()
+                    .with_context(|| "Invalid string table index in appinfo data")
+            }
+            None => self.c_string(),
+        }
+    }
+
+    /// Reads key-value pairs until the end of the current section
+    fn section(&mut self) -> Result<HashMap<String, Value>> {
+        let mut section = HashMap::new();
+
+        loop {
+            let value_type = self.u8()?;
+
+            if value_type == 0x08 {
+                return Ok(section);
+            }
+
+            let key = self.key()?;
+
+            let value = match value_type {
+                0x00 => Value::Section(self.section()?),
+                0x01 => Value::String(self.c_string()?),
+                0x02 => Value::Integer(u64::from(self.u32()?)),
+                0x03 | 0x04 | 0x06 => {
+                    self.bytes(4)?;
+                    Value::Other
+                }
+                0x07 | 0x0A => Value::Integer(self.u64()?),
+                unknown => anyhow::bail!("Unknown value type {:#04x} in appinfo data", unknown),
+            };
+
+            section.insert(key, value);
+        }
+    }
+}
+
+/// Reads the mapping from DLC app IDs to their base game's app ID from an appinfo file
+pub fn load_dlc_parents(path: &Path) -> Result<HashMap<u32, u32>> {
+    let data =
+        std::fs::read(path).with_context(|| format!("Failed to read app info from {:?}", path))?;
+
+    let mut reader = Reader {
+        data: &data,
+        position: 0,
+        string_table: None,
+    };
+
+    let magic = reader.u32()?;
+    let _universe = reader.u32()?;
+
+    if magic == MAGIC_V29 {
+        let string_table_offset = usize::try_from(reader.u64()?)?;
+        let header_end = reader.position;
+
+        reader.position = string_table_offset;
+        let string_count = reader.u32()?;
+        reader.string_table = Some(
+            (0..string_count)
+                .map(|_| reader.c_string())
+                .collect::<Result<_>>()?,
+        );
+
+        reader.position = header_end;
+    } else if magic != MAGIC_V27 && magic != MAGIC_V28 {
+        anyhow::bail!("Unsupported appinfo format {:#010x}", magic);
+    }
+
+    let mut dlc_parents = HashMap::new();
+
+    loop {
+        let appid = reader.u32()?;
+        if appid == 0 {
+            break;
+        }
+
+        let size = reader.u32()? as usize;
+        let entry_end = reader.position + size;
+
+        // Skip the info state, last updated time, PICS token, SHA-1 and change number
+        reader.bytes(4 + 4 + 8 + 20 + 4)?;
+
+        if magic != MAGIC_V27 {
+            // Skip the SHA-1 of the binary data
+            reader.bytes(20)?;
+        }
+
+        let info = Value::Section(reader.section()?);
+
+        if let Some(parent) = info
+            .lookup(&["appinfo", "common", "parent"])
+            .and_then(Value::as_u32)
+        {
+            if parent != 0 && parent != appid {
+                dlc_parents.insert(appid, parent);
+            }
+        }
+
+        reader.position = entry_end;
+    }
+
+    Ok(dlc_parents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fails_to_read_strings_past_the_end_of_the_data() {
+        let mut reader = Reader {
+            data: b"name\0",
+            position: 0,
+            string_table: None,
+        };
+
+        assert_eq!(reader.c_string().unwrap(), "name");
+        assert!(reader.c_string().is_err());
+
+        reader.position = 100;
+        assert!(reader.c_string().is_err());
+    }
+}
@@ -0,0 +1,49 @@
+//! Completing lnshot's commands and options in shells, with clap_complete.
+//!
+//! Completion scripts ask lnshot itself what to suggest each time, with the `COMPLETE` environment variable
+//! set to the shell's name, so users and app IDs can be completed from Steam's own folders.
+
+use clap::{Command, ValueEnum};
+use clap_complete::env::{Bash, EnvCompleter, Fish, Powershell, Zsh};
+use std::io::Write;
+
+/// Environment variable completion scripts set when running lnshot to complete a command line
+pub const COMPLETE_VAR: &str = "COMPLETE";
+
+/// Shells completion scripts can be written for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    Powershell,
+}
+
+impl Shell {
+    fn completer(self) -> &'static dyn EnvCompleter {
+        match self {
+            Shell::Bash => &Bash,
+            Shell::Zsh => &Zsh,
+            Shell::Fish => &Fish,
+            Shell::Powershell => &Powershell,
+        }
+    }
+}
+
+/// Which values of their own Steam users and apps can be completed with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DynamicValues {
+    /// SteamID64s of the users found, described by their display names
+    Users,
+
+    /// App IDs of the screenshot folders found, described by their names
+    Apps,
+}
+
+/// Writes the completion script for the shell, which runs the command by its name to complete each command line
+pub fn generate(shell: Shell, command: &Command, output: &mut dyn Write) -> std::io::Result<()> {
+    let name = command.get_name();
+    shell
+        .completer()
+        .write_registration(COMPLETE_VAR, name, name, name, output)
+}
@@ -0,0 +1,327 @@
+//! Controlling a running daemon through a local socket, or a named pipe on Windows
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use directories::ProjectDirs;
+use std::fmt;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Duration;
+
+/// Longest the daemon is given to answer a command
+pub(crate) const REPLY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Something a running daemon can be asked to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Command {
+    /// Show what the daemon is watching, and what it's done so far
+    Status,
+
+    /// Stop acting on changes until resumed
+    Pause,
+
+    /// Act on changes again, catching up on anything which changed while paused
+    Resume,
+
+    /// Link everything again straight away
+    Resync,
+
+    /// Read the config file again, and link everything with the new settings
+    Reload,
+
+    /// Stop the daemon
+    Stop,
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self.to_possible_value() {
+            Some(value) => write!(formatter, "{}", value.get_name()),
+            None => write!(formatter, "{:?}", self),
+        }
+    }
+}
+
+/// A command sent to the daemon, which is answered once it's been acted on
+#[derive(Debug)]
+pub struct Request {
+    pub command: Command,
+    reply_channel: mpsc::Sender<String>,
+}
+
+impl Request {
+    /// Makes a request for the command, along with where its answer will arrive
+    pub fn new(command: Command) -> (Request, mpsc::Receiver<String>) {
+        let (reply_channel, receive_channel) = mpsc::channel();
+
+        (
+            Request {
+                command,
+                reply_channel,
+            },
+            receive_channel,
+        )
+    }
+
+    /// Sends the answer back to whoever sent the command
+    pub fn reply(self, message: &str) {
+        let _ = self.reply_channel.send(message.to_string());
+    }
+}
+
+/// Held by the running daemon, removing its socket once it stops
+#[derive(Debug)]
+pub struct ControlSocket {
+    #[cfg_attr(not(unix), allow(dead_code))]
+    path: PathBuf,
+
+    /// Locked while a command is being answered
+    answering: Arc<Mutex<()>>,
+}
+
+impl Drop for ControlSocket {
+    fn drop(&mut self) {
+        // Give the answer to a command like `stop` a moment to be written before the process exits
+        for _ in 0..100 {
+            if self.answering.try_lock().is_ok() {
+                break;
+            }
+
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        #[cfg(unix)]
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Where the daemon listens for commands, such as `/run/user/1000/lnshot/daemon.sock` on Linux,
+/// or a named pipe for the current user on Windows
+pub fn socket_path() -> Option<PathBuf> {
+    if cfg!(windows) {
+        let user_name = std::env::var("USERNAME").unwrap_or_default();
+        return Some(PathBuf::from(format!(r"\\.\pipe\lnshot-{}", user_name)));
+    }
+
+    ProjectDirs::from_path(PathBuf::from("lnshot")).map(|project_dirs| {
+        project_dirs
+            .runtime_dir()
+            .unwrap_or(project_dirs.cache_dir())
+            .join("daemon.sock")
+    })
+}
+
+/// Sends a command to the running daemon, returning its answer
+pub fn send(command: Command) -> Result<String> {
+    let path = socket_path().with_context(|| "Failed to find the daemon's socket")?;
+    let mut stream = connect(&path).with_context(|| {
+        format!(
+            "Failed to connect to the daemon at {:?}; is it running?",
+            path
+        )
+    })?;
+
+    writeln!(stream, "{}", command).with_context(|| "Failed to send the command")?;
+
+    let mut reply = String::new();
+    stream
+        .read_to_string(&mut reply)
+        .with_context(|| "Failed to read the daemon's reply")?;
+
+    if reply.is_empty() {
+        anyhow::bail!("The daemon stopped before replying");
+    }
+
+    Ok(reply.trim_end().to_string())
+}
+
+/// Passes commands sent with [`send`] to the handler, from a thread of their own
+pub fn listen<F>(handler: F) -> Result<ControlSocket>
+where
+    F: Fn(Request) + Send + 'static,
+{
+    let path = socket_path().with_context(|| "Failed to find a folder for the daemon's socket")?;
+    serve(path, handler)
+}
+
+/// Reads a command from a connection, passes it to the handler, and writes back the answer
+fn answer<S: Read + Write, F: Fn(Request)>(mut stream: S, handler: &F, answering: &Mutex<()>) {
+    let mut line = Vec::new();
+    let mut byte = [0];
+
+    // Commands are short, so anything longer than this isn't one
+    while line.len() < 64 && matches!(stream.read(&mut byte), Ok(1)) && byte[0] != b'\n' {
+        line.push(byte[0]);
+    }
+
+    let _answering = answering.lock();
+    let line = String::from_utf8_lossy(&line);
+    let message = match Command::from_str(line.trim(), true) {
+        Ok(command) => {
+            let (request, receive_channel) = Request::new(command);
+            handler(request);
+
+            receive_channel
+                .recv_timeout(REPLY_TIMEOUT)
+                .unwrap_or_else(|_| "The daemon didn't reply in time".to_string())
+        }
+        Err(_) => format!("Unknown command {:?}", line.trim()),
+    };
+
+    let _ = writeln!(stream, "{}", message);
+    let _ = stream.flush();
+}
+
+#[cfg(unix)]
+fn connect(path: &std::path::Path) -> std::io::Result<std::os::unix::net::UnixStream> {
+    let stream = std::os::unix::net::UnixStream::connect(path)?;
+    stream.set_read_timeout(Some(REPLY_TIMEOUT + Duration::from_secs(5)))?;
+    Ok(stream)
+}
+
+#[cfg(unix)]
+fn serve<F>(path: PathBuf, handler: F) -> Result<ControlSocket>
+where
+    F: Fn(Request) + Send + 'static,
+{
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::net::UnixListener;
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory {:?}", parent))?;
+    }
+
+    // Only one daemon runs at a time, so any socket already there was left behind
+    let _ = std::fs::remove_file(&path);
+
+    let listener =
+        UnixListener::bind(&path).with_context(|| format!("Failed to listen on {:?}", path))?;
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))
+        .with_context(|| format!("Failed to restrict access to {:?}", path))?;
+
+    let answering = Arc::new(Mutex::new(()));
+    let thread_answering = answering.clone();
+
+    std::thread::Builder::new()
+        .name("control".to_string())
+        .spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let _ = stream.set_read_timeout(Some(Duration::from_secs(5)));
+                answer(stream, &handler, &thread_answering);
+            }
+        })
+        .with_context(|| "Failed to start the control thread")?;
+
+    Ok(ControlSocket { path, answering })
+}
+
+#[cfg(windows)]
+fn connect(path: &std::path::Path) -> std::io::Result<std::fs::File> {
+    // Another command may be being answered, in which case the pipe is busy for a moment
+    let mut attempts = 0;
+
+    loop {
+        match std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+        {
+            Err(error) if error.raw_os_error() == Some(231) && attempts < 10 => {
+                attempts += 1;
+                std::thread::sleep(Duration::from_millis(100));
+            }
+            result => return result,
+        }
+    }
+}
+
+#[cfg(windows)]
+fn serve<F>(path: PathBuf, handler: F) -> Result<ControlSocket>
+where
+    F: Fn(Request) + Send + 'static,
+{
+    use std::os::windows::ffi::OsStrExt;
+    use std::os::windows::io::FromRawHandle;
+    use windows_sys::Win32::Foundation::{
+        CloseHandle, GetLastError, ERROR_PIPE_CONNECTED, INVALID_HANDLE_VALUE,
+    };
+    use windows_sys::Win32::Storage::FileSystem::{FlushFileBuffers, PIPE_ACCESS_DUPLEX};
+    use windows_sys::Win32::System::Pipes::{
+        ConnectNamedPipe, CreateNamedPipeW, DisconnectNamedPipe, PIPE_READMODE_BYTE,
+        PIPE_REJECT_REMOTE_CLIENTS, PIPE_TYPE_BYTE, PIPE_UNLIMITED_INSTANCES, PIPE_WAIT,
+    };
+
+    let answering = Arc::new(Mutex::new(()));
+    let thread_answering = answering.clone();
+
+    let pipe_name = path
+        .as_os_str()
+        .encode_wide()
+        .chain(std::iter::once(0))
+        .collect::<Vec<_>>();
+
+    std::thread::Builder::new()
+        .name("control".to_string())
+        .spawn(move || loop {
+            let pipe = unsafe {
+                CreateNamedPipeW(
+                    pipe_name.as_ptr(),
+                    PIPE_ACCESS_DUPLEX,
+                    PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+                    PIPE_UNLIMITED_INSTANCES,
+                    4096,
+                    4096,
+                    0,
+                    std::ptr::null(),
+                )
+            };
+
+            if pipe == INVALID_HANDLE_VALUE {
+                println!(
+                    "Failed to create the control pipe: {}",
+                    std::io::Error::last_os_error()
+                );
+                return;
+            }
+
+            let connected = unsafe {
+                ConnectNamedPipe(pipe, std::ptr::null_mut()) != 0
+                    || GetLastError() == ERROR_PIPE_CONNECTED
+            };
+
+            if !connected {
+                unsafe { CloseHandle(pipe) };
+                continue;
+            }
+
+            // The file takes ownership of the pipe, closing it once the command's been answered
+            let stream = unsafe { std::fs::File::from_raw_handle(pipe) };
+            answer(&stream, &handler, &thread_answering);
+
+            // The reply has to be read before the pipe is disconnected, or it's lost
+            unsafe {
+                FlushFileBuffers(pipe);
+                DisconnectNamedPipe(pipe);
+            }
+
+            drop(stream);
+        })
+        .with_context(|| "Failed to start the control thread")?;
+
+    Ok(ControlSocket { path, answering })
+}
+
+#[cfg(not(any(unix, windows)))]
+fn connect(_path: &std::path::Path) -> std::io::Result<std::fs::File> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+#[cfg(not(any(unix, windows)))]
+fn serve<F>(_path: PathBuf, _handler: F) -> Result<ControlSocket>
+where
+    F: Fn(Request) + Send + 'static,
+{
+    anyhow::bail!("Controlling the daemon isn't supported on this platform");
+}
@@ -0,0 +1,126 @@
+//! Offering the daemon on the D-Bus session bus as `org.ticky.lnshot`, so desktop shells and scripts
+//! can control it and hear about new screenshots.
+//!
+//! The daemon answers method calls for each [`Command`], such as `Resync` and `Pause`, and emits a `NewScreenshot` signal
+//! with the screenshot's path, app ID, game name and user.
+
+use crate::control::{Command, Request, REPLY_TIMEOUT};
+use crate::hooks::NewScreenshot;
+use crate::log::Event;
+use anyhow::{Context, Result};
+use zbus::blocking::connection::{Builder, Connection};
+use zbus::{block_on, fdo, interface, SignalContext};
+
+/// The name the daemon takes on the bus
+const BUS_NAME: &str = "org.ticky.lnshot";
+
+/// Where the daemon's methods and signals are found
+const OBJECT_PATH: &str = "/org/ticky/lnshot";
+
+/// Held by the running daemon, for sending signals. The daemon leaves the bus once it stops.
+pub struct DbusService {
+    connection: Connection,
+}
+
+impl DbusService {
+    /// Emits `NewScreenshot(s path, t appid, s game, s user)`
+    pub fn send_new_screenshot(&self, screenshot: &NewScreenshot) {
+        let result = self
+            .connection
+            .object_server()
+            .interface::<_, Daemon>(OBJECT_PATH)
+            .and_then(|daemon| {
+                block_on(Daemon::new_screenshot(
+                    daemon.signal_context(),
+                    &screenshot.path.to_string_lossy(),
+                    screenshot.appid,
+                    &screenshot.game_name,
+                    &screenshot.user_name,
+                ))
+            });
+
+        if let Err(error) = result {
+            Event::error("dbus", format!("Failed to send a D-Bus signal: {}", error)).log();
+        }
+    }
+}
+
+/// The daemon's object on the bus, passing each method call on to the daemon as a [`Request`]
+struct Daemon {
+    handler: Box<dyn Fn(Request) + Send + Sync>,
+}
+
+impl Daemon {
+    /// Passes the command on to the daemon, and waits for its answer
+    fn ask(&self, command: Command) -> fdo::Result<String> {
+        let (request, receive_channel) = Request::new(command);
+        (self.handler)(request);
+
+        receive_channel
+            .recv_timeout(REPLY_TIMEOUT)
+            .map_err(|_| fdo::Error::Timeout("The daemon didn't reply in time".to_string()))
+    }
+}
+
+#[interface(name = "org.ticky.lnshot")]
+impl Daemon {
+    fn status(&self) -> fdo::Result<String> {
+        self.ask(Command::Status)
+    }
+
+    fn pause(&self) -> fdo::Result<String> {
+        self.ask(Command::Pause)
+    }
+
+    fn resume(&self) -> fdo::Result<String> {
+        self.ask(Command::Resume)
+    }
+
+    fn resync(&self) -> fdo::Result<String> {
+        self.ask(Command::Resync)
+    }
+
+    fn reload(&self) -> fdo::Result<String> {
+        self.ask(Command::Reload)
+    }
+
+    fn stop(&self) -> fdo::Result<String> {
+        self.ask(Command::Stop)
+    }
+
+    #[zbus(signal)]
+    async fn new_screenshot(
+        context: &SignalContext<'_>,
+        path: &str,
+        appid: u64,
+        game: &str,
+        user: &str,
+    ) -> zbus::Result<()>;
+}
+
+/// Connects to the session bus, takes the daemon's name, and passes method calls to the handler
+pub fn start<F>(handler: F) -> Result<DbusService>
+where
+    F: Fn(Request) + Send + Sync + 'static,
+{
+    // The name isn't queued for, as a daemon which isn't running already can't be waiting for it
+    let connection = Builder::session()
+        .and_then(|builder| {
+            builder.serve_at(
+                OBJECT_PATH,
+                Daemon {
+                    handler: Box::new(handler),
+                },
+            )
+        })
+        .and_then(|builder| builder.name(BUS_NAME))
+        .and_then(|builder| builder.build())
+        .with_context(|| {
+            format!(
+                "Failed to offer the daemon on the D-Bus session bus as {:?}",
+                BUS_NAME
+            )
+        })?;
+
+    Ok(DbusService { connection })
+}
@@ -0,0 +1,95 @@
+//! Support for the Steam Deck and other devices running SteamOS
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Folder SteamOS mounts microSD cards and other removable drives inside
+const REMOVABLE_MEDIA_DIR: &str = "/run/media";
+
+/// Checks whether this computer is running SteamOS, as the Steam Deck does
+pub fn is_steam_os() -> bool {
+    let Ok(os_release) = std::fs::read_to_string("/etc/os-release") else {
+        return false;
+    };
+
+    os_release.lines().any(|line| {
+        line.split_once('=').is_some_and(|(key, value)| {
+            key == "ID" && value.trim_matches('"').eq_ignore_ascii_case("steamos")
+        })
+    })
+}
+
+/// Finds Steam library folders on mounted microSD cards and other removable drives.
+///
+/// SteamOS mounts cards at `/run/media/<label>`, or `/run/media/<user>/<label>` on newer versions.
+pub fn removable_library_dirs() -> Vec<PathBuf> {
+    let mut library_dirs = Vec::new();
+
+    for mount_dir in child_dirs(Path::new(REMOVABLE_MEDIA_DIR)) {
+        for candidate_dir in std::iter::once(mount_dir.clone()).chain(child_dirs(&mount_dir)) {
+            if candidate_dir.join("steamapps").is_dir() {
+                library_dirs.push(candidate_dir);
+            }
+        }
+    }
+
+    library_dirs.sort();
+    library_dirs
+}
+
+/// Reads the names of games installed on removable drives from their app manifests.
+/// This includes games on cards Steam isn't currently using, which it doesn't otherwise list.
+pub fn removable_library_names() -> HashMap<u64, String> {
+    let mut names = HashMap::new();
+
+    for library_dir in removable_library_dirs() {
+        let Ok(entries) = library_dir.join("steamapps").read_dir() else {
+            continue;
+        };
+
+        for entry in entries.flatten() {
+            let file_name = entry.file_name();
+            let Some(file_name) = file_name.to_str() else {
+                continue;
+            };
+
+            if !file_name.starts_with("appmanifest_") || !file_name.ends_with(".acf") {
+                continue;
+            }
+
+            let Ok(manifest) = steamy_vdf::load(entry.path()) else {
+                continue;
+            };
+
+            let appid = manifest
+                .lookup("AppState.appid")
+                .and_then(|appid| appid.as_str())
+                .and_then(|appid_str| appid_str.parse::<u64>().ok());
+
+            let name = manifest
+                .lookup("AppState.name")
+                .or_else(|| manifest.lookup("AppState.installdir"))
+                .and_then(|name| name.as_str())
+                .filter(|name| !name.trim().is_empty());
+
+            if let (Some(appid), Some(name)) = (appid, name) {
+                names.insert(appid, name.to_string());
+            }
+        }
+    }
+
+    names
+}
+
+/// Lists the folders directly inside a folder, or nothing if it can't be read
+fn child_dirs(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = dir.read_dir() else {
+        return Vec::new();
+    };
+
+    entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir())
+        .collect()
+}
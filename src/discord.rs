@@ -0,0 +1,160 @@
+//! Sharing new screenshots to a Discord channel through one of its webhooks
+
+use crate::hooks::NewScreenshot;
+use crate::log::Event;
+use anyhow::{Context, Result};
+use reqwest::blocking::multipart::Form;
+use reqwest::blocking::Client;
+use reqwest::StatusCode;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+/// Shortest time between uploads, keeping well within Discord's limit of 30 messages a minute per channel
+const UPLOAD_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Longest to wait for each upload to finish
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How many times each screenshot is uploaded before giving up on it
+const ATTEMPTS: u32 = 3;
+
+/// Uploads new screenshots to a Discord webhook from a thread of its own, one at a time,
+/// so that bursts of screenshots don't run into Discord's rate limits or hold up the daemon
+pub struct DiscordWebhook {
+    transmit_channel: mpsc::Sender<NewScreenshot>,
+}
+
+/// Why an upload failed
+enum UploadError {
+    /// Discord asked for uploads to slow down for this long
+    RateLimited(Duration),
+
+    Failed(anyhow::Error),
+}
+
+impl DiscordWebhook {
+    /// Starts uploading to the webhook URL
+    pub fn start(url: String) -> Result<DiscordWebhook> {
+        let (transmit_channel, receive_channel) = mpsc::channel::<NewScreenshot>();
+        let client = Client::builder()
+            .timeout(TIMEOUT)
+            .build()
+            .with_context(|| "Failed to set up the Discord webhook's HTTP client")?;
+
+        std::thread::Builder::new()
+            .name("discord".to_string())
+            .spawn(move || {
+                let mut last_upload = None::<Instant>;
+
+                for screenshot in receive_channel {
+                    for attempt in 1..=ATTEMPTS {
+                        if let Some(last_upload) = last_upload {
+                            std::thread::sleep(
+                                UPLOAD_INTERVAL.saturating_sub(last_upload.elapsed()),
+                            );
+                        }
+
+                        last_upload = Some(Instant::now());
+
+                        let error = match upload(&client, &url, &screenshot) {
+                            Ok(()) => break,
+                            Err(UploadError::RateLimited(retry_after)) => {
+                                std::thread::sleep(retry_after);
+                                anyhow::anyhow!(
+                                    "Discord asked to wait {:.1} second(s) first",
+                                    retry_after.as_secs_f64()
+                                )
+                            }
+                            Err(UploadError::Failed(error)) => error,
+                        };
+
+                        if attempt == ATTEMPTS {
+                            Event::error(
+                                "discord",
+                                format!(
+                                    "Giving up on uploading {:?} to Discord: {:#}",
+                                    screenshot.path, error
+                                ),
+                            )
+                            .path(&screenshot.path)
+                            .log();
+                        } else {
+                            Event::warn(
+                                "discord",
+                                format!(
+                                    "Failed to upload {:?} to Discord, so trying again: {:#}",
+                                    screenshot.path, error
+                                ),
+                            )
+                            .path(&screenshot.path)
+                            .log();
+                        }
+                    }
+                }
+            })
+            .with_context(|| "Failed to start the Discord thread")?;
+
+        Ok(DiscordWebhook { transmit_channel })
+    }
+
+    /// Queues a screenshot to be uploaded, along with an embed naming the game and who took it
+    pub fn send(&self, screenshot: &NewScreenshot) {
+        let _ = self.transmit_channel.send(screenshot.clone());
+    }
+}
+
+fn upload(client: &Client, url: &str, screenshot: &NewScreenshot) -> Result<(), UploadError> {
+    let file_name = screenshot
+        .path
+        .file_name()
+        .map(|file_name| file_name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    // The embed shows the uploaded file by referring to it by name
+    let payload = serde_json::json!({
+        "embeds": [{
+            "title": screenshot.game_name,
+            "description": format!("Taken by {}", screenshot.user_name),
+            "image": { "url": format!("attachment://{}", file_name) },
+        }],
+    });
+
+    let form = Form::new()
+        .text("payload_json", payload.to_string())
+        .file("files[0]", &screenshot.path)
+        .with_context(|| format!("Failed to read {:?}", screenshot.path))
+        .map_err(UploadError::Failed)?;
+
+    let response = client
+        .post(url)
+        .multipart(form)
+        .send()
+        .with_context(|| "Failed to reach Discord")
+        .map_err(UploadError::Failed)?;
+
+    let status = response.status();
+
+    if status.is_success() {
+        return Ok(());
+    }
+
+    let response = response.text().unwrap_or_default();
+
+    if status == StatusCode::TOO_MANY_REQUESTS {
+        let retry_after = serde_json::from_str::<serde_json::Value>(&response)
+            .ok()
+            .and_then(|response| response.get("retry_after")?.as_f64())
+            .filter(|seconds| *seconds >= 0.0)
+            .unwrap_or(5.0);
+
+        return Err(UploadError::RateLimited(Duration::from_secs_f64(
+            retry_after.min(60.0),
+        )));
+    }
+
+    Err(UploadError::Failed(anyhow::anyhow!(
+        "Discord responded with {}: {}",
+        status,
+        response.trim()
+    )))
+}
@@ -0,0 +1,234 @@
+//! Diagnostics for the environment lnshot runs in, with suggested fixes for any problems found
+
+use crate::locator::{locate_steam, ScreenshotLocator};
+use crate::watcher::WATCH_LIMIT_FIX;
+use anyhow::Result;
+use directories::UserDirs;
+use std::path::Path;
+use std::time::Duration;
+
+/// The outcome of a single check
+enum Outcome {
+    /// Everything is fine, with details of what was found
+    Ok(String),
+
+    /// Something is wrong, along with how to fix it
+    Problem { detail: String, fix: String },
+}
+
+/// Collects and prints the outcome of each check
+#[derive(Default)]
+struct Report {
+    problem_count: usize,
+}
+
+impl Report {
+    fn add(&mut self, check: &str, outcome: Outcome) {
+        match outcome {
+            Outcome::Ok(detail) => println!("[ok]      {}: {}", check, detail),
+            Outcome::Problem { detail, fix } => {
+                self.problem_count += 1;
+                println!("[problem] {}: {}", check, detail);
+                println!("          Fix: {}", fix);
+            }
+        }
+    }
+}
+
+/// Checks that Steam and the Pictures folder can be found, and that symlinks and
+/// file system watching work, printing the results along with fixes for any problems.
+///
+/// Fails if any problems were found.
+pub fn run(
+    pictures_directory_name: &str,
+    steam_path: Option<&Path>,
+    target_dir: Option<&Path>,
+) -> Result<()> {
+    let mut report = Report::default();
+
+    let steam_dir = locate_steam(steam_path);
+
+    report.add(
+        "Steam installation",
+        match &steam_dir {
+            Ok(steam_dir) => Outcome::Ok(format!("found at {:?}", steam_dir.path)),
+            Err(error) if steam_path.is_some() => Outcome::Problem {
+                detail: format!("{:#}", error),
+                fix: "Check the path given with `--steam-path` or the `steam_path` config option"
+                    .to_string(),
+            },
+            Err(_) => Outcome::Problem {
+                detail: "Steam couldn't be found on this computer".to_string(),
+                fix: "Install Steam, and run it at least once so it can finish setting itself up, or give its location with `--steam-path`"
+                    .to_string(),
+            },
+        },
+    );
+
+    let locator = steam_dir
+        .as_ref()
+        .ok()
+        .map(|steam_dir| ScreenshotLocator::new(&steam_dir.path));
+
+    if let Some(locator) = &locator {
+        let steam_user_data_dir = locator.user_data_dir();
+
+        report.add(
+            "Steam userdata folder",
+            match steam_user_data_dir.read_dir() {
+                Ok(entries) => Outcome::Ok(format!(
+                    "readable at {:?}, with {} item(s)",
+                    steam_user_data_dir,
+                    entries.count()
+                )),
+                Err(error) => Outcome::Problem {
+                    detail: format!("{:?} can't be read: {}", steam_user_data_dir, error),
+                    fix: "Log into Steam at least once, and make sure your user account can read Steam's folder"
+                        .to_string(),
+                },
+            },
+        );
+
+        report.add(
+            "Steam users",
+            match locator.users() {
+                Ok(users) if users.is_empty() => Outcome::Problem {
+                    detail: "No users have logged into Steam on this computer".to_string(),
+                    fix: "Log into Steam at least once".to_string(),
+                },
+                Ok(users) => Outcome::Ok(format!(
+                    "found {}",
+                    users
+                        .iter()
+                        .map(|user| format!("{:?} ({})", user.persona_name, user.steamid_str))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )),
+                Err(error) => Outcome::Problem {
+                    detail: format!("Steam's list of users can't be read: {:#}", error),
+                    fix: "Log into Steam at least once, so it records who has logged in"
+                        .to_string(),
+                },
+            },
+        );
+    }
+
+    let picture_dir =
+        UserDirs::new().and_then(|user_dirs| user_dirs.picture_dir().map(Path::to_path_buf));
+
+    // A target directory replaces the Pictures folder entirely, so check where it would be created instead
+    let target_parent_dir = target_dir.map(|target_dir| {
+        if target_dir.is_dir() {
+            target_dir.to_path_buf()
+        } else {
+            target_dir.parent().unwrap_or(target_dir).to_path_buf()
+        }
+    });
+
+    if let (Some(target_dir), Some(target_parent_dir)) = (target_dir, &target_parent_dir) {
+        report.add(
+            "Target folder",
+            if target_parent_dir.is_dir() {
+                Outcome::Ok(format!("screenshots will be linked into {:?}", target_dir))
+            } else {
+                Outcome::Problem {
+                    detail: format!("{:?} doesn't exist", target_parent_dir),
+                    fix: format!(
+                        "Create the folder {:?}, or check the path given with `--target-dir`",
+                        target_parent_dir
+                    ),
+                }
+            },
+        );
+    } else {
+        report.add(
+            "Pictures folder",
+            match &picture_dir {
+                Some(picture_dir) if picture_dir.is_dir() => Outcome::Ok(format!(
+                    "found at {:?}; screenshots will be linked into {:?}",
+                    picture_dir,
+                    picture_dir.join(pictures_directory_name)
+                )),
+                Some(picture_dir) => Outcome::Problem {
+                    detail: format!("{:?} doesn't exist", picture_dir),
+                    fix: format!("Create the folder {:?}", picture_dir),
+                },
+                None => Outcome::Problem {
+                    detail: "Your Pictures folder couldn't be found".to_string(),
+                    fix: if cfg!(target_os = "linux") {
+                        "Set XDG_PICTURES_DIR in ~/.config/user-dirs.dirs, for example with `xdg-user-dirs-update --set PICTURES ~/Pictures`"
+                            .to_string()
+                    } else {
+                        "Make sure your user account has a Pictures folder".to_string()
+                    },
+                },
+            },
+        );
+    }
+
+    // Make a test symlink next to where screenshots will be linked, as support can differ between file systems
+    let symlink_test_dir = target_parent_dir
+        .or(picture_dir)
+        .filter(|test_dir| test_dir.is_dir())
+        .unwrap_or_else(std::env::temp_dir);
+    let symlink_test_path = symlink_test_dir.join(format!(".lnshot-doctor-{}", std::process::id()));
+
+    report.add(
+        "Symlink creation",
+        match symlink::symlink_dir(&symlink_test_dir, &symlink_test_path) {
+            Ok(_) => {
+                let _ = symlink::remove_symlink_dir(&symlink_test_path);
+                Outcome::Ok(format!("symlinks can be created in {:?}", symlink_test_dir))
+            }
+            Err(error) => Outcome::Problem {
+                detail: format!(
+                    "A symlink couldn't be created in {:?}: {}",
+                    symlink_test_dir, error
+                ),
+                fix: if cfg!(windows) {
+                    "Turn on Developer Mode in Settings, under \"For developers\", or run lnshot as an administrator"
+                        .to_string()
+                } else {
+                    "Make sure the folder is writable and on a file system which supports symlinks, or use `--mode copy`"
+                        .to_string()
+                },
+            },
+        },
+    );
+
+    if let Some(locator) = &locator {
+        use notify_debouncer_mini::{new_debouncer, notify::RecursiveMode};
+
+        let steam_user_data_dir = locator.user_data_dir();
+        let (transmit_channel, _receive_channel) = std::sync::mpsc::channel();
+
+        let watch_result =
+            new_debouncer(Duration::from_secs(1), transmit_channel).and_then(|mut debouncer| {
+                debouncer
+                    .watcher()
+                    .watch(&steam_user_data_dir, RecursiveMode::Recursive)
+            });
+
+        report.add(
+            "File system watching",
+            match watch_result {
+                Ok(_) => Outcome::Ok(format!("{:?} can be watched", steam_user_data_dir)),
+                Err(error) => Outcome::Problem {
+                    detail: format!("{:?} can't be watched: {}", steam_user_data_dir, error),
+                    fix: if cfg!(target_os = "linux") {
+                        WATCH_LIMIT_FIX.to_string()
+                    } else {
+                        "Run `lnshot` periodically instead of using daemon mode".to_string()
+                    },
+                },
+            },
+        );
+    }
+
+    if report.problem_count > 0 {
+        anyhow::bail!("Found {} problem(s)", report.problem_count);
+    }
+
+    println!("No problems found");
+    Ok(())
+}
@@ -0,0 +1,62 @@
+//! Exit codes telling scripts why lnshot stopped, beyond just whether it succeeded
+
+use std::fmt;
+
+/// Anything else which went wrong, such as a config file which couldn't be read
+pub const GENERAL_ERROR: u8 = 1;
+
+/// Failures with exit codes of their own, attached to errors as context
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Failure {
+    /// Steam wasn't found on this computer, or at the path given
+    SteamNotFound,
+
+    /// No Steam users were found, with `--fail-if-empty`
+    NoUsers,
+
+    /// No app screenshot folders were found for any user, with `--fail-if-empty`
+    NoScreenshotFolders,
+
+    /// Everything else was processed, but some users or apps were skipped after errors,
+    /// or some links or folders couldn't be made or removed
+    PartialFailure,
+
+    /// The daemon couldn't watch for changes
+    Watcher,
+}
+
+impl Failure {
+    /// The code lnshot exits with. 2 is left for mistakes on the command line, as clap uses it.
+    pub fn exit_code(self) -> u8 {
+        match self {
+            Failure::SteamNotFound => 3,
+            Failure::NoUsers => 4,
+            Failure::NoScreenshotFolders => 5,
+            Failure::PartialFailure => 6,
+            Failure::Watcher => 7,
+        }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str(match self {
+            Failure::SteamNotFound => "Failed to locate Steam",
+            Failure::NoUsers => "No Steam users were found",
+            Failure::NoScreenshotFolders => {
+                "No app screenshot folders were found for any Steam user"
+            }
+            Failure::PartialFailure => "Some changes couldn't be made",
+            Failure::Watcher => "Failed to watch for changes",
+        })
+    }
+}
+
+impl std::error::Error for Failure {}
+
+/// The code to exit with for an error, from the failure it was given as context, if any
+pub fn code(error: &anyhow::Error) -> u8 {
+    error
+        .downcast_ref::<Failure>()
+        .map_or(GENERAL_ERROR, |failure| failure.exit_code())
+}
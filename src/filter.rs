@@ -0,0 +1,108 @@
+//! Choosing which apps get linked, by app ID or by name
+
+use crate::naming::AppNameResolver;
+use anyhow::Result;
+use std::convert::Infallible;
+use std::str::FromStr;
+
+/// Matches apps by their app ID, or by a glob of their name such as `Wallpaper*`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AppPattern {
+    AppId(u64),
+    Name(String),
+}
+
+impl FromStr for AppPattern {
+    type Err = Infallible;
+
+    /// Reads an app ID if the value is a number, or a name glob otherwise
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Ok(match value.parse() {
+            Ok(appid) => AppPattern::AppId(appid),
+            Err(_) => AppPattern::Name(value.to_string()),
+        })
+    }
+}
+
+impl AppPattern {
+    fn matches(&self, appid: u64, app_name: Option<&str>) -> bool {
+        match self {
+            AppPattern::AppId(pattern_appid) => *pattern_appid == appid,
+            AppPattern::Name(pattern) => {
+                app_name.is_some_and(|app_name| glob_matches(pattern, app_name))
+            }
+        }
+    }
+}
+
+/// Decides which apps are linked
+#[derive(Debug, Clone, Default)]
+pub struct AppFilter {
+    /// If any are given, only apps matching one of these are linked
+    pub include: Vec<AppPattern>,
+
+    /// Apps matching any of these are never linked
+    pub exclude: Vec<AppPattern>,
+}
+
+impl AppFilter {
+    /// Checks whether an app should be linked, matching name globs against its resolved name
+    pub fn allows(&self, appid: u64, app_name_resolver: &AppNameResolver) -> Result<bool> {
+        if self.include.is_empty() && self.exclude.is_empty() {
+            return Ok(true);
+        }
+
+        let app_name = app_name_resolver
+            .resolve(appid)?
+            .map(|app_name| app_name.to_string_lossy().into_owned());
+        let app_name = app_name.as_deref();
+
+        let included = self.include.is_empty()
+            || self
+                .include
+                .iter()
+                .any(|pattern| pattern.matches(appid, app_name));
+
+        Ok(included
+            && !self
+                .exclude
+                .iter()
+                .any(|pattern| pattern.matches(appid, app_name)))
+    }
+}
+
+/// Matches text against a glob, where `*` matches any run of characters and `?` matches any one.
+/// Matching ignores case, as app names aren't consistently capitalised.
+fn glob_matches(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.to_lowercase().chars().collect::<Vec<_>>();
+    let text = text.to_lowercase().chars().collect::<Vec<_>>();
+
+    let (mut pattern_index, mut text_index) = (0, 0);
+    let mut last_star = None;
+
+    while text_index < text.len() {
+        match pattern.get(pattern_index) {
+            Some('*') => {
+                last_star = Some((pattern_index, text_index));
+                pattern_index += 1;
+            }
+            Some(&character) if character == '?' || character == text[text_index] => {
+                pattern_index += 1;
+                text_index += 1;
+            }
+            _ => match last_star {
+                // Let the last star match one more character, and try again from there
+                Some((star_index, star_text_index)) => {
+                    pattern_index = star_index + 1;
+                    text_index = star_text_index + 1;
+                    last_star = Some((star_index, star_text_index + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[pattern_index..]
+        .iter()
+        .all(|character| *character == '*')
+}
@@ -0,0 +1,487 @@
+use std::collections::HashSet;
+
+mod compositions;
+
+use compositions::COMPOSITIONS;
+
+/// Longest folder name, in bytes, which all common file systems will accept
+const MAX_FOLDER_NAME_BYTES: usize = 255;
+
+/// Characters which aren't allowed in file names on at least one supported platform
+const ILLEGAL_CHARACTERS: [char; 9] = ['/', '\\', ':', '*', '?', '"', '<', '>', '|'];
+
+/// Names Windows reserves for devices, which can't be used for files or folders, even with an extension
+const RESERVED_NAMES: [&str; 32] = [
+    "CON", "PRN", "AUX", "NUL", "CONIN$", "CONOUT$", "COM0", "COM1", "COM2", "COM3", "COM4",
+    "COM5", "COM6", "COM7", "COM8", "COM9", "COM¹", "COM²", "COM³", "LPT0", "LPT1", "LPT2", "LPT3",
+    "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9", "LPT¹", "LPT²", "LPT³",
+];
+
+/// Character used in place of anything which can't appear in a folder name, unless another is chosen
+pub const DEFAULT_REPLACEMENT_CHARACTER: char = '_';
+
+/// Turns a game or user name into a folder name which is valid on every platform,
+/// and which doesn't clash with any of the names in `taken`.
+///
+/// The transformations are applied in a fixed order:
+///
+/// 1. Unicode is normalised, so letters followed by combining accents become
+///    the single characters most systems use for them, and any kind of space
+///    becomes a plain space. Leading and trailing whitespace is removed from the name.
+/// 2. Control characters and characters which are illegal in file names are replaced
+/// 3. Any prefix and suffix are added around the name
+/// 4. The name is truncated so the whole folder name fits within file system limits,
+///    without splitting a character from any combining marks which follow it.
+///    The prefix and suffix are kept whole, unless they don't fit on their own.
+/// 5. Trailing dots (which Windows silently drops) and spaces are removed, and
+///    names Windows reserves for devices, like `CON` or `COM1`, have the
+///    replacement character added to them
+/// 6. If the name is already taken, a numeric suffix like ` (2)` is added,
+///    truncating the name further if needed to make room for it
+pub fn finalize_folder_name(name: &str, taken: &HashSet<String>) -> String {
+    finalize_folder_name_replacing(name, taken, DEFAULT_REPLACEMENT_CHARACTER)
+}
+
+/// Turns a name into a folder name like [`finalize_folder_name`], putting the given character
+/// in place of anything which can't appear in a folder name
+pub fn finalize_folder_name_replacing(
+    name: &str,
+    taken: &HashSet<String>,
+    replacement_character: char,
+) -> String {
+    finalize_affixed_folder_name("", name, "", taken, replacement_character)
+}
+
+/// Turns a name into a folder name like [`finalize_folder_name`], with a prefix and suffix around it,
+/// such as the parts of a name template either side of `{name}`. Only the name itself is shortened
+/// to make room, so whatever the prefix and suffix add, such as an app ID, isn't cut off.
+pub fn finalize_affixed_folder_name(
+    prefix: &str,
+    name: &str,
+    suffix: &str,
+    taken: &HashSet<String>,
+    replacement_character: char,
+) -> String {
+    let prepare = |part: &str| {
+        sanitize_characters(&normalize_whitespace(&compose(part)), replacement_character)
+    };
+
+    let prefix = prepare(prefix);
+    let name = prepare(name.trim());
+    let suffix = prepare(suffix);
+
+    let folder_name = assemble(&prefix, &name, &suffix, "", replacement_character);
+    if !taken.contains(&folder_name) {
+        return folder_name;
+    }
+
+    (2..)
+        .map(|index| {
+            assemble(
+                &prefix,
+                &name,
+                &suffix,
+                &format!(" ({})", index),
+                replacement_character,
+            )
+        })
+        .find(|candidate| !taken.contains(candidate))
+        .expect("Ran out of folder name suffixes")
+}
+
+/// Puts the parts of a folder name together, shortening the name so everything fits,
+/// and adding `counter` to the end once the rest is a valid name
+fn assemble(
+    prefix: &str,
+    name: &str,
+    suffix: &str,
+    counter: &str,
+    replacement_character: char,
+) -> String {
+    let max_bytes = MAX_FOLDER_NAME_BYTES - counter.len();
+    let room = max_bytes.saturating_sub(prefix.len() + suffix.len());
+
+    let assembled = format!("{}{}{}", prefix, truncate(name, room), suffix);
+    // This only cuts anything when the prefix and suffix are too long by themselves
+    let assembled = truncate(&assembled, max_bytes).trim_start();
+
+    format!("{}{}", finish(assembled, replacement_character), counter)
+}
+
+/// Hangul syllables are composed from their leading consonants, vowels and trailing consonants arithmetically
+const HANGUL_SYLLABLE_BASE: u32 = 0xAC00;
+const HANGUL_LEADING_BASE: u32 = 0x1100;
+const HANGUL_VOWEL_BASE: u32 = 0x1161;
+const HANGUL_TRAILING_BASE: u32 = 0x11A7;
+const HANGUL_LEADING_COUNT: u32 = 19;
+const HANGUL_VOWEL_COUNT: u32 = 21;
+const HANGUL_TRAILING_COUNT: u32 = 28;
+
+/// The single character a character and the one following it compose into, if any
+fn compose_pair(first: char, second: char) -> Option<char> {
+    let (first_code, second_code) = (u32::from(first), u32::from(second));
+
+    let leading = first_code.wrapping_sub(HANGUL_LEADING_BASE);
+    let vowel = second_code.wrapping_sub(HANGUL_VOWEL_BASE);
+    if leading < HANGUL_LEADING_COUNT && vowel < HANGUL_VOWEL_COUNT {
+        return char::from_u32(
+            HANGUL_SYLLABLE_BASE + (leading * HANGUL_VOWEL_COUNT + vowel) * HANGUL_TRAILING_COUNT,
+        );
+    }
+
+    let syllable = first_code.wrapping_sub(HANGUL_SYLLABLE_BASE);
+    let trailing = second_code.wrapping_sub(HANGUL_TRAILING_BASE);
+    if syllable < HANGUL_LEADING_COUNT * HANGUL_VOWEL_COUNT * HANGUL_TRAILING_COUNT
+        && syllable % HANGUL_TRAILING_COUNT == 0
+        && (1..HANGUL_TRAILING_COUNT).contains(&trailing)
+    {
+        return char::from_u32(first_code + trailing);
+    }
+
+    COMPOSITIONS
+        .binary_search_by_key(&(first, second), |&(base, mark, _composed)| (base, mark))
+        .ok()
+        .map(|index| COMPOSITIONS[index].2)
+}
+
+/// Composes letters followed by combining marks into single characters, as Unicode's NFC normalisation does,
+/// so a name gives the same folder name however it was typed or stored, such as decomposed by macOS.
+/// Marks which can't be composed are kept after the character they follow.
+fn compose(name: &str) -> String {
+    let mut composed = String::with_capacity(name.len());
+    let mut starter: Option<char> = None;
+    let mut uncomposed_marks = String::new();
+
+    for character in name.chars() {
+        if let Some(previous) = starter {
+            if uncomposed_marks.is_empty() || is_combining(character) {
+                if let Some(composed_character) = compose_pair(previous, character) {
+                    starter = Some(composed_character);
+                    continue;
+                }
+            }
+
+            if is_combining(character) {
+                uncomposed_marks.push(character);
+                continue;
+            }
+
+            composed.push(previous);
+            composed.push_str(&uncomposed_marks);
+            uncomposed_marks.clear();
+        }
+
+        starter = Some(character);
+    }
+
+    composed.extend(starter);
+    composed.push_str(&uncomposed_marks);
+    composed
+}
+
+/// Checks whether a name has no visible characters in it,
+/// so would produce a meaningless folder name
+pub fn is_blank(name: &str) -> bool {
+    name.chars().all(|character| {
+        character.is_whitespace()
+            || character.is_control()
+            // Zero width spaces and joiners, word joiner, and byte order mark
+            || matches!(character, '\u{200B}'..='\u{200D}' | '\u{2060}' | '\u{FEFF}')
+    })
+}
+
+/// Replaces all whitespace with plain spaces
+fn normalize_whitespace(name: &str) -> String {
+    name.chars()
+        .map(|character| {
+            if character.is_whitespace() {
+                ' '
+            } else {
+                character
+            }
+        })
+        .collect()
+}
+
+/// Checks whether a character can stand in for characters which can't appear in folder names
+pub fn is_valid_replacement_character(character: char) -> bool {
+    !character.is_control()
+        && !character.is_whitespace()
+        && character != '.'
+        && !ILLEGAL_CHARACTERS.contains(&character)
+}
+
+/// Replaces characters which can't appear in folder names
+fn sanitize_characters(name: &str, replacement_character: char) -> String {
+    name.chars()
+        .map(|character| {
+            if character.is_control() || ILLEGAL_CHARACTERS.contains(&character) {
+                replacement_character
+            } else {
+                character
+            }
+        })
+        .collect()
+}
+
+/// Makes a whole folder name valid, removing trailing dots and spaces, and changing names Windows reserves
+fn finish(name: &str, replacement_character: char) -> String {
+    let trimmed = name.trim_end_matches(['.', ' ']);
+
+    // A name made entirely of dots would refer to the current or parent directory
+    if trimmed.is_empty() {
+        return replacement_character.to_string();
+    }
+
+    // Device names are reserved whatever extension follows them, so only the part before the first dot matters
+    let stem = trimmed[..trimmed.find('.').unwrap_or(trimmed.len())].trim_end_matches(' ');
+
+    if RESERVED_NAMES
+        .iter()
+        .any(|reserved_name| reserved_name.eq_ignore_ascii_case(stem))
+    {
+        format!(
+            "{}{}{}",
+            stem,
+            replacement_character,
+            &trimmed[stem.len()..]
+        )
+    } else {
+        trimmed.to_string()
+    }
+}
+
+/// Whether a character attaches to the one before it, and so can't be separated from it
+fn is_combining(character: char) -> bool {
+    matches!(
+        u32::from(character),
+        0x0300..=0x036F // Combining Diacritical Marks
+            | 0x1AB0..=0x1AFF // Combining Diacritical Marks Extended
+            | 0x1DC0..=0x1DFF // Combining Diacritical Marks Supplement
+            | 0x200D // Zero Width Joiner
+            | 0x20D0..=0x20FF // Combining Diacritical Marks for Symbols
+            | 0x3099..=0x309A // Combining Katakana-Hiragana Voiced Sound Marks
+            | 0xFE00..=0xFE0F // Variation Selectors
+            | 0xFE20..=0xFE2F // Combining Half Marks
+            | 0x1F3FB..=0x1F3FF // Emoji Skin Tone Modifiers
+    )
+}
+
+/// Shortens a name to at most `max_bytes`, only cutting between whole characters
+/// and never separating a character from its combining marks
+fn truncate(name: &str, max_bytes: usize) -> &str {
+    if name.len() <= max_bytes {
+        return name;
+    }
+
+    let mut end = max_bytes;
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    loop {
+        let next = name[end..].chars().next();
+        let previous = name[..end].chars().next_back();
+
+        match (previous, next) {
+            (Some(previous), Some(next)) if is_combining(next) || u32::from(previous) == 0x200D => {
+                end -= previous.len_utf8();
+            }
+            _ => break,
+        }
+    }
+
+    name[..end].trim_end_matches(['.', ' '])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn finalize(name: &str, taken: &[&str]) -> String {
+        finalize_folder_name(name, &taken.iter().map(|name| name.to_string()).collect())
+    }
+
+    fn finalize_affixed(prefix: &str, name: &str, suffix: &str, taken: &[&str]) -> String {
+        finalize_affixed_folder_name(
+            prefix,
+            name,
+            suffix,
+            &taken.iter().map(|name| name.to_string()).collect(),
+            DEFAULT_REPLACEMENT_CHARACTER,
+        )
+    }
+
+    #[test]
+    fn composes_combining_marks() {
+        assert_eq!(finalize("Poke\u{301}mon", &[]), "Pok\u{e9}mon");
+        assert_eq!(finalize("Pok\u{e9}mon", &[]), "Pok\u{e9}mon");
+        // Marks compose one at a time, in the order Unicode puts them
+        assert_eq!(finalize("Tie\u{323}\u{302}ng", &[]), "Ti\u{1ec7}ng");
+        assert_eq!(finalize("\u{30ab}\u{3099}", &[]), "\u{30ac}");
+        assert_eq!(finalize("\u{1112}\u{1161}\u{11ab}", &[]), "\u{d55c}");
+        // Marks with nothing to compose into are kept
+        assert_eq!(finalize("q\u{301}", &[]), "q\u{301}");
+    }
+
+    #[test]
+    fn normalizes_whitespace() {
+        assert_eq!(finalize("  Half-Life\u{a0}2\t", &[]), "Half-Life 2");
+    }
+
+    #[test]
+    fn replaces_illegal_characters() {
+        assert_eq!(
+            finalize("Portal: Still Alive?", &[]),
+            "Portal_ Still Alive_"
+        );
+        assert_eq!(finalize("a/b\\c\u{7}", &[]), "a_b_c_");
+        assert_eq!(
+            finalize_folder_name_replacing("a/b", &HashSet::new(), '-'),
+            "a-b"
+        );
+    }
+
+    #[test]
+    fn removes_trailing_dots() {
+        assert_eq!(finalize("Game...", &[]), "Game");
+        assert_eq!(finalize("...", &[]), "_");
+        assert_eq!(finalize("..", &[]), "_");
+    }
+
+    #[test]
+    fn changes_reserved_names() {
+        assert_eq!(finalize("CON", &[]), "CON_");
+        assert_eq!(finalize("com1.txt", &[]), "com1_.txt");
+        assert_eq!(finalize("CONSOLE", &[]), "CONSOLE");
+    }
+
+    #[test]
+    fn truncates_long_names() {
+        let long_name = "a".repeat(300);
+        assert_eq!(finalize(&long_name, &[]), "a".repeat(MAX_FOLDER_NAME_BYTES));
+
+        // Two byte characters can't be cut in half
+        let long_name = "\u{e9}".repeat(200);
+        assert_eq!(finalize(&long_name, &[]), "\u{e9}".repeat(127));
+    }
+
+    #[test]
+    fn truncates_without_separating_combining_marks() {
+        // Marks which can't be composed stay with the character before them
+        let long_name = format!("{}q\u{301}", "a".repeat(254));
+        assert_eq!(finalize(&long_name, &[]), "a".repeat(254));
+    }
+
+    #[test]
+    fn truncates_after_sanitizing() {
+        // Each replaced character is shorter than the one it replaces, so more of the name fits
+        let long_name = "\u{7}".repeat(300);
+        assert_eq!(finalize(&long_name, &[]), "_".repeat(MAX_FOLDER_NAME_BYTES));
+    }
+
+    #[test]
+    fn truncates_after_composing() {
+        // Composed, each letter takes two bytes rather than three, so the whole name fits
+        let long_name = "e\u{301}".repeat(120);
+        assert_eq!(finalize(&long_name, &[]), "\u{e9}".repeat(120));
+    }
+
+    #[test]
+    fn removes_trailing_dots_left_by_truncating() {
+        let long_name = format!("{}.{}", "a".repeat(254), "b".repeat(10));
+        assert_eq!(finalize(&long_name, &[]), "a".repeat(254));
+    }
+
+    #[test]
+    fn disambiguates_taken_names() {
+        assert_eq!(finalize("Portal", &["Portal"]), "Portal (2)");
+        assert_eq!(finalize("Portal", &["Portal", "Portal (2)"]), "Portal (3)");
+        assert_eq!(finalize("Portal", &["Portal (2)"]), "Portal");
+    }
+
+    #[test]
+    fn disambiguates_after_sanitizing() {
+        assert_eq!(finalize("Portal?", &["Portal_"]), "Portal_ (2)");
+        assert_eq!(finalize("Game.", &["Game"]), "Game (2)");
+        assert_eq!(finalize("CON", &["CON_"]), "CON_ (2)");
+    }
+
+    #[test]
+    fn disambiguates_after_composing() {
+        assert_eq!(
+            finalize("Poke\u{301}mon", &["Pok\u{e9}mon"]),
+            "Pok\u{e9}mon (2)"
+        );
+    }
+
+    #[test]
+    fn truncates_to_make_room_for_disambiguation() {
+        let long_name = "a".repeat(300);
+        let truncated = "a".repeat(MAX_FOLDER_NAME_BYTES);
+
+        let disambiguated = finalize(&long_name, &[&truncated]);
+        assert_eq!(disambiguated, format!("{} (2)", "a".repeat(251)));
+        assert_eq!(disambiguated.len(), MAX_FOLDER_NAME_BYTES);
+    }
+
+    #[test]
+    fn keeps_prefixes_and_suffixes() {
+        assert_eq!(
+            finalize_affixed("[620] ", " Portal 2 ", " (Tester)", &[]),
+            "[620] Portal 2 (Tester)"
+        );
+        // Affixes are sanitized, but their spaces are kept
+        assert_eq!(
+            finalize_affixed("", "Portal", " [A/B]", &[]),
+            "Portal [A_B]"
+        );
+    }
+
+    #[test]
+    fn truncates_names_between_prefixes_and_suffixes() {
+        let long_name = "a".repeat(300);
+
+        let folder_name = finalize_affixed("", &long_name, " [620]", &[]);
+        assert_eq!(folder_name, format!("{} [620]", "a".repeat(249)));
+        assert_eq!(folder_name.len(), MAX_FOLDER_NAME_BYTES);
+
+        let folder_name = finalize_affixed("620 - ", &long_name, "", &[]);
+        assert!(folder_name.starts_with("620 - a"));
+        assert_eq!(folder_name.len(), MAX_FOLDER_NAME_BYTES);
+    }
+
+    #[test]
+    fn truncates_affixes_which_are_too_long_by_themselves() {
+        let long_suffix = "b".repeat(300);
+
+        assert_eq!(
+            finalize_affixed("", "Portal", &long_suffix, &[]),
+            "b".repeat(MAX_FOLDER_NAME_BYTES)
+        );
+    }
+
+    #[test]
+    fn removes_trailing_dots_from_affixed_names() {
+        assert_eq!(finalize_affixed("", "Game.", " [1]", &[]), "Game. [1]");
+        assert_eq!(finalize_affixed("", "Game", " v1.", &[]), "Game v1");
+    }
+
+    #[test]
+    fn disambiguates_affixed_names_after_their_suffixes() {
+        let long_name = "a".repeat(300);
+        let taken = format!("{} [620]", "a".repeat(249));
+
+        let folder_name = finalize_affixed("", &long_name, " [620]", &[&taken]);
+        assert_eq!(folder_name, format!("{} [620] (2)", "a".repeat(245)));
+        assert_eq!(folder_name.len(), MAX_FOLDER_NAME_BYTES);
+    }
+
+    #[test]
+    fn detects_blank_names() {
+        assert!(is_blank(""));
+        assert!(is_blank(" \t\u{a0}"));
+        assert!(is_blank("\u{200b}\u{feff}"));
+        assert!(!is_blank(" a "));
+    }
+}
@@ -0,0 +1,218 @@
+//! Running commands of the user's choosing when the daemon notices new screenshots
+
+use crate::log::Event;
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::borrow::Cow;
+use std::fmt;
+use std::path::PathBuf;
+use std::process::Command;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A screenshot which appeared while the daemon was watching
+#[derive(Debug, Clone)]
+pub struct NewScreenshot {
+    /// Where the screenshot is inside Steam's `userdata` directory
+    pub path: PathBuf,
+
+    pub appid: u64,
+
+    /// Name of the game, or its app ID if it couldn't be named
+    pub game_name: String,
+
+    /// Display name of the user who took it
+    pub user_name: String,
+}
+
+/// A new screenshot as it's described in JSON
+#[derive(Serialize)]
+struct ScreenshotJson<'a> {
+    appid: u64,
+    game: &'a str,
+    user: &'a str,
+    path: Cow<'a, str>,
+    timestamp: u64,
+}
+
+impl NewScreenshot {
+    /// Describes the screenshot as JSON, such as
+    /// `{"appid": 220, "game": "Half-Life 2", "user": "Gabe", "path": "...", "timestamp": 1700000000}`.
+    /// The timestamp is when the screenshot was found, in seconds since the Unix epoch.
+    pub fn to_json(&self) -> String {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0);
+
+        serde_json::to_string(&ScreenshotJson {
+            appid: self.appid,
+            game: &self.game_name,
+            user: &self.user_name,
+            path: self.path.to_string_lossy(),
+            timestamp,
+        })
+        .expect("screenshots are always serialisable, as their paths are written lossily")
+    }
+}
+
+/// A command to run for each new screenshot, such as `my-script {path} {appid} {game}`.
+///
+/// The command is split into words like a shell would, including quoting with `"` or `'`,
+/// but isn't run by a shell. `{path}`, `{appid}`, `{game}` and `{user}` are replaced within each word,
+/// so they're passed along whole even if they contain spaces.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HookCommand {
+    words: Vec<String>,
+}
+
+impl FromStr for HookCommand {
+    type Err = anyhow::Error;
+
+    fn from_str(command: &str) -> Result<Self> {
+        let words = split_words(command)
+            .with_context(|| format!("Hook command {:?} has an unclosed quote", command))?;
+
+        if words.is_empty() {
+            anyhow::bail!("Hook command is empty");
+        }
+
+        for word in &words {
+            let mut rest = word.as_str();
+
+            while let Some(start) = rest.find('{') {
+                let end = rest[start..]
+                    .find('}')
+                    .with_context(|| format!("Hook command {:?} has an unclosed {{", command))?;
+
+                match &rest[start + 1..start + end] {
+                    "path" | "appid" | "game" | "user" => {}
+                    placeholder => anyhow::bail!(
+                        "Hook command {:?} has an unknown placeholder {{{}}}; use {{path}}, {{appid}}, {{game}} or {{user}}",
+                        command,
+                        placeholder
+                    ),
+                }
+
+                rest = &rest[start + end + 1..];
+            }
+        }
+
+        Ok(HookCommand { words })
+    }
+}
+
+impl fmt::Display for HookCommand {
+    /// Writes the command back out, quoting any words which need it
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        let words = self
+            .words
+            .iter()
+            .map(|word| {
+                let needs_quotes = word.is_empty()
+                    || word.contains(|character: char| {
+                        character.is_whitespace() || matches!(character, '"' | '\'')
+                    });
+
+                if !needs_quotes {
+                    word.clone()
+                } else if word.contains('"') {
+                    format!("'{}'", word)
+                } else {
+                    format!("\"{}\"", word)
+                }
+            })
+            .collect::<Vec<_>>();
+
+        formatter.write_str(&words.join(" "))
+    }
+}
+
+impl HookCommand {
+    /// Starts the command for a screenshot, without waiting for it to finish.
+    /// The details are also passed as `LNSHOT_PATH`, `LNSHOT_APPID`, `LNSHOT_GAME` and `LNSHOT_USER`.
+    pub fn run(&self, screenshot: &NewScreenshot) -> Result<()> {
+        let mut words = self.words.iter().map(|word| render(word, screenshot));
+        let program = words
+            .next()
+            .expect("hook commands are checked to not be empty");
+
+        let mut child = Command::new(&program)
+            .args(words)
+            .env("LNSHOT_PATH", &screenshot.path)
+            .env("LNSHOT_APPID", screenshot.appid.to_string())
+            .env("LNSHOT_GAME", &screenshot.game_name)
+            .env("LNSHOT_USER", &screenshot.user_name)
+            .spawn()
+            .with_context(|| format!("Failed to run hook {:?}", program))?;
+
+        // Waiting from another thread means slow hooks don't hold up the daemon, but are still cleaned up
+        std::thread::spawn(move || match child.wait() {
+            Ok(status) if !status.success() => {
+                Event::error("hook", format!("Hook {:?} exited with {}", program, status)).log()
+            }
+            Ok(_) => {}
+            Err(error) => Event::error(
+                "hook",
+                format!("Failed to wait for hook {:?}: {}", program, error),
+            )
+            .log(),
+        });
+
+        Ok(())
+    }
+}
+
+/// Fills in a word's placeholders for a screenshot
+fn render(word: &str, screenshot: &NewScreenshot) -> String {
+    let mut rendered = String::new();
+    let mut rest = word;
+
+    // Placeholders are filled in one pass, so names which look like placeholders are left alone
+    while let Some((before, after)) = rest.split_once('{') {
+        let (placeholder, after) = after
+            .split_once('}')
+            .expect("hook commands are checked for unclosed placeholders when parsed");
+
+        rendered.push_str(before);
+
+        match placeholder {
+            "path" => rendered.push_str(&screenshot.path.to_string_lossy()),
+            "appid" => rendered.push_str(&screenshot.appid.to_string()),
+            "game" => rendered.push_str(&screenshot.game_name),
+            _ => rendered.push_str(&screenshot.user_name),
+        }
+
+        rest = after;
+    }
+
+    rendered.push_str(rest);
+    rendered
+}
+
+/// Splits a command into words at whitespace, except within quotes, returning `None` if a quote isn't closed
+fn split_words(command: &str) -> Option<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word = None::<String>;
+    let mut quote = None;
+
+    for character in command.chars() {
+        match (quote, character) {
+            (Some(open), character) if character == open => quote = None,
+            (Some(_), character) => word.get_or_insert_with(String::new).push(character),
+            (None, '"' | '\'') => {
+                quote = Some(character);
+                word.get_or_insert_with(String::new);
+            }
+            (None, character) if character.is_whitespace() => words.extend(word.take()),
+            (None, character) => word.get_or_insert_with(String::new).push(character),
+        }
+    }
+
+    if quote.is_some() {
+        return None;
+    }
+
+    words.extend(word);
+    Some(words)
+}
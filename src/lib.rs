@@ -0,0 +1,52 @@
+//! Symlink your Steam games' screenshot directories into your Pictures folder.
+//!
+//! A [`ScreenshotLocator`] finds Steam users and their screenshot folders,
+//! a [`LinkPlanner`] works out what each folder should be called,
+//! and a [`Linker`] makes the folders inside the managed directory.
+
+#[macro_use]
+extern crate lazy_static;
+
+pub mod app_list;
+pub mod appinfo;
+pub mod completions;
+pub mod config;
+pub mod control;
+#[cfg(target_os = "linux")]
+pub mod dbus;
+pub mod deck;
+pub mod discord;
+pub mod doctor;
+pub mod exit;
+pub mod filter;
+pub mod folder_name;
+pub mod hooks;
+pub mod linker;
+pub mod locator;
+pub mod log;
+pub mod manifest;
+pub mod metrics;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+pub mod naming;
+pub mod notification;
+pub mod operations;
+pub mod parallel;
+pub mod pid_file;
+pub mod plan;
+pub mod report;
+pub mod service;
+pub mod signals;
+pub mod snapshot;
+pub mod systemd;
+pub mod table;
+#[cfg(feature = "tray")]
+pub mod tray;
+pub mod user_selector;
+pub mod watcher;
+pub mod web_names;
+pub mod webhook;
+
+pub use linker::{LinkMode, LinkPlanner, LinkStatus, Linker, PlannedLink};
+pub use locator::{ScreenshotLocator, SteamUser};
+pub use naming::{AppNameResolver, LookedUpNames, NameSource};
@@ -0,0 +1,1462 @@
+use crate::folder_name::{
+    finalize_affixed_folder_name, finalize_folder_name, DEFAULT_REPLACEMENT_CHARACTER,
+};
+use crate::log::Event;
+use crate::manifest::Manifest;
+use crate::naming::{AppNameResolver, NameTemplate};
+use crate::operations::Operations;
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use std::collections::HashSet;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::fs::Metadata;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// How screenshots are made available inside the managed directory
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LinkMode {
+    /// Symlink each game's screenshot folder
+    Symlink,
+
+    /// Copy new and changed screenshots into a folder for each game.
+    /// Useful for cloud sync tools which don't follow symlinks.
+    Copy,
+
+    /// Make a Finder alias to each game's screenshot folder, for apps which don't follow symlinks.
+    /// Only available on macOS.
+    Alias,
+}
+
+/// Kind of link made on Windows, where symlinks need Developer Mode or administrator rights.
+/// Other systems always use symlinks.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WindowsLinkType {
+    /// Make symlinks, or directory junctions instead if symlinks aren't allowed
+    Auto,
+
+    /// Only ever make symlinks
+    Symlink,
+
+    /// Only ever make directory junctions, which don't need any special rights but always use absolute paths
+    Junction,
+}
+
+/// Width and height in pixels, read from `WxH`, such as `1920x1080`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Resolution {
+    pub width: u64,
+    pub height: u64,
+}
+
+impl FromStr for Resolution {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        value
+            .split_once(['x', 'X'])
+            .and_then(|(width, height)| {
+                Some(Resolution {
+                    width: width.trim().parse().ok()?,
+                    height: height.trim().parse().ok()?,
+                })
+            })
+            .with_context(|| {
+                format!(
+                    "Resolution {:?} should be a width and height such as \"1920x1080\"",
+                    value
+                )
+            })
+    }
+}
+
+impl fmt::Display for Resolution {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{}x{}", self.width, self.height)
+    }
+}
+
+/// A file size in bytes, read from a number which may end with `K`, `M` or `G`, such as `200K`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileSize(pub u64);
+
+impl FromStr for FileSize {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        let (number, multiplier) = match value.char_indices().last() {
+            Some((index, 'k' | 'K')) => (&value[..index], 1024),
+            Some((index, 'm' | 'M')) => (&value[..index], 1024 * 1024),
+            Some((index, 'g' | 'G')) => (&value[..index], 1024 * 1024 * 1024),
+            _ => (value, 1),
+        };
+
+        let size = number
+            .trim()
+            .parse::<u64>()
+            .with_context(|| format!("File size {:?} should be a size such as \"200K\"", value))?;
+
+        Ok(FileSize(size.saturating_mul(multiplier)))
+    }
+}
+
+impl fmt::Display for FileSize {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            size if size > 0 && size % (1024 * 1024 * 1024) == 0 => {
+                write!(formatter, "{}G", size / (1024 * 1024 * 1024))
+            }
+            size if size > 0 && size % (1024 * 1024) == 0 => {
+                write!(formatter, "{}M", size / (1024 * 1024))
+            }
+            size if size > 0 && size % 1024 == 0 => write!(formatter, "{}K", size / 1024),
+            size => write!(formatter, "{}", size),
+        }
+    }
+}
+
+/// Which screenshots are copied in copy mode, leaving out thumbnails and tiny captures.
+/// Symlinks share whole folders, so they can't leave anything out.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CopyFilter {
+    /// Screenshots narrower or shorter than this aren't copied
+    pub min_resolution: Option<Resolution>,
+
+    /// Screenshots smaller than this aren't copied
+    pub min_file_size: Option<FileSize>,
+}
+
+impl CopyFilter {
+    /// Whether the file is too small to copy. Files whose dimensions can't be read are copied anyway,
+    /// as they may not be images lnshot knows how to read.
+    fn excludes(&self, path: &Path, metadata: &Metadata) -> bool {
+        if let Some(FileSize(min_file_size)) = self.min_file_size {
+            if metadata.len() < min_file_size {
+                return true;
+            }
+        }
+
+        if let Some(min_resolution) = self.min_resolution {
+            match imagesize::size(path) {
+                Ok(size) => {
+                    return (size.width as u64) < min_resolution.width
+                        || (size.height as u64) < min_resolution.height;
+                }
+                Err(error) => {
+                    Event::debug(
+                        "copy",
+                        format!(
+                            "Couldn't read the dimensions of {:?}, so copying it anyway: {}",
+                            path, error
+                        ),
+                    )
+                    .path(path)
+                    .log();
+                }
+            }
+        }
+
+        false
+    }
+}
+
+/// A folder to be made for an app inside a user's folder
+#[derive(Debug, Clone)]
+pub struct PlannedLink {
+    pub appid: u64,
+
+    /// The app's screenshot folder inside Steam's `userdata` directory
+    pub source: PathBuf,
+
+    /// Name of the folder, which is unique among the user's other folders
+    pub name: String,
+}
+
+impl PlannedLink {
+    /// Whether the folder is named after the app's ID, as its name isn't known
+    pub fn is_numeric(&self) -> bool {
+        self.name == self.appid.to_string()
+    }
+}
+
+/// What currently exists where a planned folder should be
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkStatus {
+    /// Nothing exists there yet
+    Missing,
+
+    /// A symlink, which is broken if its target doesn't exist
+    Symlink { target: PathBuf, broken: bool },
+
+    /// A real folder, such as a placeholder or a copy
+    Folder,
+
+    /// Something other than a folder or symlink
+    Other,
+}
+
+impl LinkStatus {
+    /// Checks what exists at the given path, without following symlinks
+    pub fn of(path: &Path) -> Result<LinkStatus> {
+        let metadata = match path.symlink_metadata() {
+            Ok(metadata) => metadata,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(LinkStatus::Missing)
+            }
+            Err(error) => return Err(error).with_context(|| format!("Failed to check {:?}", path)),
+        };
+
+        Ok(if metadata.is_symlink() {
+            LinkStatus::Symlink {
+                target: std::fs::read_link(path)?,
+                broken: !path.exists(),
+            }
+        } else if metadata.is_dir() {
+            LinkStatus::Folder
+        } else {
+            LinkStatus::Other
+        })
+    }
+}
+
+/// Works out the folder names for a user's apps, making sure no two apps share a name
+pub struct LinkPlanner<'a> {
+    app_name_resolver: &'a AppNameResolver<'a>,
+    taken_names: HashSet<String>,
+
+    /// Pattern for the names of apps whose names are known, along with the user's display name
+    name_template: Option<(&'a NameTemplate, &'a str)>,
+}
+
+impl<'a> LinkPlanner<'a> {
+    pub fn new(app_name_resolver: &'a AppNameResolver<'a>) -> LinkPlanner<'a> {
+        LinkPlanner {
+            app_name_resolver,
+            taken_names: HashSet::new(),
+            name_template: None,
+        }
+    }
+
+    /// Names folders for apps with known names using a template, for the user with the given display name.
+    /// Apps without names are still named after their app ID, so they can be renamed once their names are known.
+    pub fn with_name_template(
+        mut self,
+        name_template: Option<&'a NameTemplate>,
+        user_name: &'a str,
+    ) -> LinkPlanner<'a> {
+        self.name_template = name_template.map(|name_template| (name_template, user_name));
+        self
+    }
+
+    /// Treats these names as already used, such as those of the user's other apps' folders,
+    /// when planning an app on its own rather than along with all the user's other apps
+    pub fn with_taken_names(mut self, names: impl IntoIterator<Item = String>) -> LinkPlanner<'a> {
+        self.taken_names.extend(names);
+        self
+    }
+
+    /// Names the folder for an app's screenshot folder, after the app if its name is known.
+    /// The name won't be reused for any other app planned afterwards.
+    pub fn plan(&mut self, appid: u64, source: &Path) -> Result<PlannedLink> {
+        Ok(PlannedLink {
+            appid,
+            source: source.to_path_buf(),
+            name: self.plan_name(appid)?,
+        })
+    }
+
+    /// Names the folder for an app's screenshot folder like [`LinkPlanner::plan`], with the given words added
+    /// in brackets to tell it apart from another folder for the same app, such as another Steam installation's
+    pub fn plan_distinct(
+        &mut self,
+        appid: u64,
+        source: &Path,
+        distinction: &str,
+    ) -> Result<PlannedLink> {
+        let name = self.plan_name(appid)?;
+        self.taken_names.remove(&name);
+
+        let name = finalize_affixed_folder_name(
+            "",
+            &name,
+            &format!(" ({})", distinction),
+            &self.taken_names,
+            DEFAULT_REPLACEMENT_CHARACTER,
+        );
+        self.taken_names.insert(name.clone());
+
+        Ok(PlannedLink {
+            appid,
+            source: source.to_path_buf(),
+            name,
+        })
+    }
+
+    /// Names the folder for an app, such as a placeholder, like [`LinkPlanner::plan`]
+    pub fn plan_name(&mut self, appid: u64) -> Result<String> {
+        let name = match self.app_name_resolver.resolve(appid)? {
+            Some(app_name) => {
+                let app_name = app_name.to_string_lossy();
+
+                match self.name_template {
+                    Some((name_template, user_name)) => {
+                        match name_template.render_affixes(appid, user_name) {
+                            Some((prefix, suffix)) => finalize_affixed_folder_name(
+                                &prefix,
+                                &app_name,
+                                &suffix,
+                                &self.taken_names,
+                                DEFAULT_REPLACEMENT_CHARACTER,
+                            ),
+                            None => finalize_folder_name(
+                                &name_template.render(appid, &app_name, user_name),
+                                &self.taken_names,
+                            ),
+                        }
+                    }
+                    None => finalize_folder_name(&app_name, &self.taken_names),
+                }
+            }
+            None => finalize_folder_name(&appid.to_string(), &self.taken_names),
+        };
+
+        self.taken_names.insert(name.clone());
+
+        Ok(name)
+    }
+}
+
+/// Running totals of what a [`Linker`] has done, such as for the daemon's metrics
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LinkStats {
+    /// Links, aliases and copied folders newly made
+    pub created: u64,
+
+    /// Links and aliases removed
+    pub removed: u64,
+
+    /// Changes to the file system which failed, and were skipped
+    pub errors: u64,
+}
+
+/// Makes planned folders inside users' folders, by symlinking or copying
+pub struct Linker {
+    operations: Operations,
+    mode: LinkMode,
+
+    /// Whether symlinks point to their targets with relative paths
+    relative_links: bool,
+
+    windows_link_type: WindowsLinkType,
+
+    /// Which screenshots are copied in copy mode
+    copy_filter: CopyFilter,
+
+    stats: LinkStats,
+}
+
+impl Linker {
+    pub fn new(operations: Operations, mode: LinkMode) -> Linker {
+        Linker {
+            operations,
+            mode,
+            relative_links: false,
+            windows_link_type: WindowsLinkType::Auto,
+            copy_filter: CopyFilter::default(),
+            stats: LinkStats::default(),
+        }
+    }
+
+    /// Chooses the kind of link made on Windows
+    pub fn with_windows_link_type(mut self, windows_link_type: WindowsLinkType) -> Linker {
+        self.windows_link_type = windows_link_type;
+        self
+    }
+
+    /// Makes symlinks with paths relative to the folders they're in, rather than absolute paths
+    pub fn with_relative_links(mut self, relative_links: bool) -> Linker {
+        self.relative_links = relative_links;
+        self
+    }
+
+    /// Leaves screenshots the filter excludes out of copies
+    pub fn with_copy_filter(mut self, copy_filter: CopyFilter) -> Linker {
+        self.copy_filter = copy_filter;
+        self
+    }
+
+    /// The operations used to make changes to the file system
+    pub fn operations(&mut self) -> &mut Operations {
+        &mut self.operations
+    }
+
+    /// What this linker has done so far
+    pub fn stats(&self) -> LinkStats {
+        self.stats
+    }
+
+    /// Returns a linker which makes links the same way, for use on another thread,
+    /// with nothing done yet. It's joined back with [`Linker::join`].
+    pub fn fork(&self) -> Linker {
+        Linker {
+            operations: self.operations.fork(),
+            mode: self.mode,
+            relative_links: self.relative_links,
+            windows_link_type: self.windows_link_type,
+            copy_filter: self.copy_filter,
+            stats: LinkStats::default(),
+        }
+    }
+
+    /// Adds what a linker made with [`Linker::fork`] has done to what this one has
+    pub fn join(&mut self, other: Linker) -> Result<()> {
+        self.stats.created += other.stats.created;
+        self.stats.removed += other.stats.removed;
+        self.stats.errors += other.stats.errors;
+
+        self.operations
+            .join(other.operations)
+            .with_context(|| "Failed to write to script file")
+    }
+
+    /// Creates a user's folder if it doesn't exist yet
+    pub fn create_user_dir(&mut self, target_screenshots_dir: &Path) -> Result<()> {
+        if !target_screenshots_dir.is_dir() {
+            self.operations.create_dir_all(target_screenshots_dir)?;
+        }
+
+        Ok(())
+    }
+
+    /// Moves a user's folder to a new name, such as after they've changed their display name.
+    /// Nothing is moved if the old folder is gone, or if something already has the new name.
+    ///
+    /// Returns whether the folder was moved.
+    pub fn move_user_dir(
+        &mut self,
+        steamid_str: &str,
+        previous_dir: &Path,
+        target_screenshots_dir: &Path,
+    ) -> Result<bool> {
+        if LinkStatus::of(previous_dir)? != LinkStatus::Folder {
+            return Ok(false);
+        }
+
+        if LinkStatus::of(target_screenshots_dir)? != LinkStatus::Missing {
+            Event::warn(
+                "move_user_dir",
+                format!(
+                    "Not moving {:?} to {:?}, as something is already there",
+                    previous_dir, target_screenshots_dir
+                ),
+            )
+            .user(steamid_str)
+            .path(previous_dir)
+            .path(target_screenshots_dir)
+            .log();
+            return Ok(false);
+        }
+
+        Event::info(
+            "move_user_dir",
+            format!(
+                "Moving {:?} to {:?}, as the user's display name has changed",
+                previous_dir, target_screenshots_dir
+            ),
+        )
+        .user(steamid_str)
+        .path(previous_dir)
+        .path(target_screenshots_dir)
+        .log();
+
+        self.operations
+            .rename(previous_dir, target_screenshots_dir)
+            .with_context(|| format!("Failed to move {:?}", previous_dir))?;
+
+        Ok(true)
+    }
+
+    /// Symlinks or copies an app's screenshot directory into a user's target directory,
+    /// recording what was made in the manifest. Symlinks which already point to the right place,
+    /// and symlinks lnshot didn't make, are left alone.
+    ///
+    /// Returns whether a new folder was created for the app, rather than an existing one being updated.
+    pub fn link(
+        &mut self,
+        steamid_str: &str,
+        planned_link: &PlannedLink,
+        target_screenshots_dir: &Path,
+        manifest: &mut Manifest,
+    ) -> Result<bool> {
+        let appid = planned_link.appid;
+        let steam_app_screenshot_path = &planned_link.source;
+        let target_symlink_path = target_screenshots_dir.join(&planned_link.name);
+
+        if target_symlink_path.is_symlink() && !manifest.owns_link(&target_symlink_path) {
+            Event::warn(
+                "link",
+                format!(
+                    "Leaving {:?} alone, as lnshot didn't make it",
+                    target_symlink_path
+                ),
+            )
+            .user(steamid_str)
+            .appid(appid)
+            .path(&target_symlink_path)
+            .log();
+            return Ok(false);
+        }
+
+        if self.mode == LinkMode::Copy {
+            let created = self.copy_screenshot_dir(
+                steamid_str,
+                appid,
+                steam_app_screenshot_path,
+                &target_symlink_path,
+            )?;
+
+            manifest.record_link(
+                &target_symlink_path,
+                steam_app_screenshot_path,
+                appid,
+                steamid_str,
+            );
+
+            if created {
+                self.stats.created += 1;
+            }
+
+            return Ok(created);
+        }
+
+        if self.mode == LinkMode::Alias {
+            return self.alias_screenshot_dir(
+                steamid_str,
+                appid,
+                steam_app_screenshot_path,
+                &target_symlink_path,
+                manifest,
+            );
+        }
+
+        let symlink_existed = target_symlink_path.is_symlink();
+
+        let link_target = if self.relative_links {
+            relative_path(target_screenshots_dir, steam_app_screenshot_path)
+        } else {
+            steam_app_screenshot_path.clone()
+        };
+
+        // Leave links which already point to the right place untouched, rather than recreating them.
+        // Junctions always point to absolute paths, so those are fine on Windows too.
+        let existing_target = read_link_target(&target_symlink_path);
+        if symlink_existed
+            && (existing_target.as_ref() == Some(&link_target)
+                || (cfg!(windows) && existing_target.as_ref() == Some(steam_app_screenshot_path)))
+        {
+            manifest.record_link(
+                &target_symlink_path,
+                steam_app_screenshot_path,
+                appid,
+                steamid_str,
+            );
+            return Ok(false);
+        }
+
+        Event::debug(
+            "link",
+            format!("target_symlink_path: {:?}", target_symlink_path),
+        )
+        .user(steamid_str)
+        .appid(appid)
+        .path(&target_symlink_path)
+        .path(steam_app_screenshot_path)
+        .log();
+
+        if symlink_existed {
+            // Replace the symlink in one step, so it's never missing if lnshot is interrupted
+            match self.make_link(
+                &link_target,
+                steam_app_screenshot_path,
+                &target_symlink_path,
+                true,
+            ) {
+                Ok(_) => manifest.record_link(
+                    &target_symlink_path,
+                    steam_app_screenshot_path,
+                    appid,
+                    steamid_str,
+                ),
+                Err(error) => {
+                    Event::error(
+                        "link",
+                        format!(
+                            "Error replacing symlink {:?} with one to {:?}: {}",
+                            target_symlink_path, steam_app_screenshot_path, error
+                        ),
+                    )
+                    .user(steamid_str)
+                    .appid(appid)
+                    .path(&target_symlink_path)
+                    .path(steam_app_screenshot_path)
+                    .log();
+                    self.stats.errors += 1;
+                }
+            }
+
+            return Ok(false);
+        }
+
+        if target_symlink_path.is_dir() {
+            // Upgrade placeholder folders to symlinks now there are screenshots to show.
+            // `remove_dir` only removes empty directories, so anything with content is left alone.
+            match self.operations.remove_dir(&target_symlink_path) {
+                Ok(_) => Event::info(
+                    "link",
+                    format!("Replacing placeholder folder {:?}", target_symlink_path),
+                )
+                .user(steamid_str)
+                .appid(appid)
+                .path(&target_symlink_path)
+                .log(),
+                Err(error) => {
+                    Event::error(
+                        "link",
+                        format!(
+                            "Error removing placeholder folder {:?}: {}",
+                            target_symlink_path, error
+                        ),
+                    )
+                    .user(steamid_str)
+                    .appid(appid)
+                    .path(&target_symlink_path)
+                    .log();
+                    self.stats.errors += 1;
+                }
+            };
+        }
+
+        match self.make_link(
+            &link_target,
+            steam_app_screenshot_path,
+            &target_symlink_path,
+            false,
+        ) {
+            Ok(_) => {
+                manifest.record_link(
+                    &target_symlink_path,
+                    steam_app_screenshot_path,
+                    appid,
+                    steamid_str,
+                );
+                self.stats.created += 1;
+                Ok(true)
+            }
+            Err(error) => {
+                Event::error(
+                    "link",
+                    format!(
+                        "Error symlinking {:?} to {:?}: {}",
+                        steam_app_screenshot_path, target_symlink_path, error
+                    ),
+                )
+                .user(steamid_str)
+                .appid(appid)
+                .path(&target_symlink_path)
+                .path(steam_app_screenshot_path)
+                .log();
+                self.stats.errors += 1;
+
+                Ok(false)
+            }
+        }
+    }
+
+    /// Makes a link to a folder, replacing any existing link if `replace` is set.
+    ///
+    /// On Windows, this may be a directory junction rather than a symlink, depending on the Windows link type.
+    /// Junctions can't have relative paths, so they point to `absolute_target` instead of `target`.
+    fn make_link(
+        &mut self,
+        target: &Path,
+        absolute_target: &Path,
+        link: &Path,
+        replace: bool,
+    ) -> std::io::Result<()> {
+        if cfg!(windows) && self.windows_link_type == WindowsLinkType::Junction {
+            if replace {
+                self.operations.remove_symlink(link)?;
+            }
+
+            return self.operations.junction_dir(absolute_target, link);
+        }
+
+        let result = if replace {
+            self.operations.replace_symlink_dir(target, link)
+        } else {
+            self.operations.symlink_dir(target, link)
+        };
+
+        match result {
+            Err(error)
+                if cfg!(windows)
+                    && self.windows_link_type == WindowsLinkType::Auto
+                    && is_privilege_error(&error) =>
+            {
+                Event::warn(
+                    "link",
+                    format!(
+                        "Symlinks aren't allowed here, so making a directory junction for {:?} instead. \
+                        Turning on Developer Mode in Windows' settings allows symlinks.",
+                        link
+                    ),
+                )
+                .path(link)
+                .log();
+
+                // Replacing a symlink on Windows removes the old one first, so it may already be gone
+                if link.is_symlink() {
+                    self.operations.remove_symlink(link)?;
+                }
+
+                self.operations.junction_dir(absolute_target, link)
+            }
+            result => result,
+        }
+    }
+
+    /// Makes a Finder alias to an app's screenshot directory, replacing any symlink, alias
+    /// or empty placeholder folder lnshot made there before. Aliases already pointing to the right place are left alone.
+    ///
+    /// Returns whether the alias was newly created.
+    fn alias_screenshot_dir(
+        &mut self,
+        steamid_str: &str,
+        appid: u64,
+        steam_app_screenshot_path: &Path,
+        target_path: &Path,
+        manifest: &mut Manifest,
+    ) -> Result<bool> {
+        let status = LinkStatus::of(target_path)?;
+
+        match &status {
+            LinkStatus::Missing => {}
+            LinkStatus::Symlink { .. } => self.operations.remove_symlink(target_path)?,
+            LinkStatus::Folder => {
+                if let Err(error) = self.operations.remove_dir(target_path) {
+                    Event::error(
+                        "alias",
+                        format!(
+                            "Error removing placeholder folder {:?}: {}",
+                            target_path, error
+                        ),
+                    )
+                    .user(steamid_str)
+                    .appid(appid)
+                    .path(target_path)
+                    .log();
+                    self.stats.errors += 1;
+                    return Ok(false);
+                }
+            }
+            LinkStatus::Other => match manifest.link(target_path) {
+                Some(link_record) if link_record.source == steam_app_screenshot_path => {
+                    return Ok(false)
+                }
+                Some(_) => self.operations.remove_alias(target_path)?,
+                None => {
+                    Event::warn(
+                        "alias",
+                        format!("Leaving {:?} alone, as lnshot didn't make it", target_path),
+                    )
+                    .user(steamid_str)
+                    .appid(appid)
+                    .path(target_path)
+                    .log();
+                    return Ok(false);
+                }
+            },
+        }
+
+        Event::info(
+            "alias",
+            format!(
+                "Making alias {:?} to {:?}",
+                target_path, steam_app_screenshot_path
+            ),
+        )
+        .user(steamid_str)
+        .appid(appid)
+        .path(target_path)
+        .path(steam_app_screenshot_path)
+        .log();
+
+        match self
+            .operations
+            .alias_dir(steam_app_screenshot_path, target_path)
+        {
+            Ok(_) => {
+                manifest.record_link(target_path, steam_app_screenshot_path, appid, steamid_str);
+
+                if status == LinkStatus::Missing {
+                    self.stats.created += 1;
+                }
+
+                Ok(status == LinkStatus::Missing)
+            }
+            Err(error) => {
+                Event::error(
+                    "alias",
+                    format!(
+                        "Error making alias {:?} to {:?}: {}",
+                        target_path, steam_app_screenshot_path, error
+                    ),
+                )
+                .user(steamid_str)
+                .appid(appid)
+                .path(target_path)
+                .path(steam_app_screenshot_path)
+                .log();
+                self.stats.errors += 1;
+                Ok(false)
+            }
+        }
+    }
+
+    /// Copies new and changed screenshots from an app's screenshot directory into a target folder.
+    /// Subfolders, such as Steam's thumbnails, aren't copied, nor are screenshots the copy filter excludes.
+    ///
+    /// Returns whether the target folder was newly created.
+    fn copy_screenshot_dir(
+        &mut self,
+        steamid_str: &str,
+        appid: u64,
+        steam_app_screenshot_path: &Path,
+        target_path: &Path,
+    ) -> Result<bool> {
+        Event::debug("copy", format!("target_path: {:?}", target_path))
+            .user(steamid_str)
+            .appid(appid)
+            .path(target_path)
+            .path(steam_app_screenshot_path)
+            .log();
+
+        let created = !target_path.exists() && !target_path.is_symlink();
+
+        // Replace any symlink left over from symlink mode with a real folder
+        if target_path.is_symlink() {
+            self.operations.remove_symlink(target_path)?;
+        }
+
+        if !target_path.is_dir() {
+            self.operations.create_dir_all(target_path)?;
+        }
+
+        if !steam_app_screenshot_path.is_dir() {
+            return Ok(created);
+        }
+
+        let mut copied_count = 0;
+        let mut filtered_count = 0;
+
+        for entry in steam_app_screenshot_path.read_dir()? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+
+            if !metadata.is_file() {
+                continue;
+            }
+
+            if self.copy_filter.excludes(&entry.path(), &metadata) {
+                filtered_count += 1;
+                continue;
+            }
+
+            let destination_path = target_path.join(entry.file_name());
+
+            // Copies keep the original's modification time, so matching size and time means it's unchanged
+            if let Ok(destination_metadata) = destination_path.metadata() {
+                if destination_metadata.len() == metadata.len()
+                    && destination_metadata.modified().ok() == metadata.modified().ok()
+                {
+                    continue;
+                }
+            }
+
+            match self.operations.copy_file(&entry.path(), &destination_path) {
+                Ok(_) => copied_count += 1,
+                Err(error) => {
+                    Event::error(
+                        "copy",
+                        format!(
+                            "Error copying {:?} to {:?}: {}",
+                            entry.path(),
+                            destination_path,
+                            error
+                        ),
+                    )
+                    .user(steamid_str)
+                    .appid(appid)
+                    .path(entry.path())
+                    .path(&destination_path)
+                    .log();
+                    self.stats.errors += 1;
+                }
+            }
+        }
+
+        let message = if filtered_count > 0 {
+            format!(
+                "Copied {} new or changed screenshot(s), leaving out {} below the minimum resolution or file size",
+                copied_count, filtered_count
+            )
+        } else {
+            format!("Copied {} new or changed screenshot(s)", copied_count)
+        };
+
+        Event::info("copy", message)
+            .user(steamid_str)
+            .appid(appid)
+            .path(target_path)
+            .log();
+
+        Ok(created)
+    }
+
+    /// Creates an empty placeholder folder for an app which doesn't have any screenshots yet,
+    /// unless something already has its name. The folder is recorded in the manifest, so `clean` knows lnshot made it.
+    pub fn create_placeholder(
+        &mut self,
+        steamid_str: &str,
+        appid: u64,
+        folder_name: &str,
+        target_screenshots_dir: &Path,
+        manifest: &mut Manifest,
+    ) -> Result<()> {
+        let placeholder_path = target_screenshots_dir.join(folder_name);
+
+        if placeholder_path.exists() || placeholder_path.is_symlink() {
+            return Ok(());
+        }
+
+        Event::info(
+            "placeholder",
+            format!("Creating placeholder folder {:?}", placeholder_path),
+        )
+        .user(steamid_str)
+        .appid(appid)
+        .path(&placeholder_path)
+        .log();
+
+        self.operations.create_dir(&placeholder_path)?;
+        manifest.record_placeholder(&placeholder_path, steamid_str);
+        Ok(())
+    }
+
+    /// Removes a symlink or alias lnshot made, such as once the screenshot folder it points to has gone.
+    /// Copied screenshots are left alone, as they may be the only copies left.
+    pub fn remove_link(
+        &mut self,
+        steamid_str: &str,
+        appid: u64,
+        link_path: &Path,
+        manifest: &mut Manifest,
+    ) -> Result<()> {
+        let result = match LinkStatus::of(link_path)? {
+            LinkStatus::Symlink { .. } => self.operations.remove_symlink(link_path),
+            LinkStatus::Other => self.operations.remove_alias(link_path),
+            LinkStatus::Missing => {
+                manifest.forget_link(link_path);
+                return Ok(());
+            }
+            LinkStatus::Folder => return Ok(()),
+        };
+
+        Event::info(
+            "unlink",
+            format!(
+                "Removing {:?}, as the app's screenshot folder is gone",
+                link_path
+            ),
+        )
+        .user(steamid_str)
+        .appid(appid)
+        .path(link_path)
+        .log();
+
+        match result {
+            Ok(_) => {
+                manifest.forget_link(link_path);
+                self.stats.removed += 1;
+            }
+            Err(error) => {
+                Event::error(
+                    "unlink",
+                    format!("Error unlinking {:?}: {}", link_path, error),
+                )
+                .user(steamid_str)
+                .appid(appid)
+                .path(link_path)
+                .log();
+                self.stats.errors += 1;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Removes symlinks and aliases lnshot made in a user's folder which point to the same screenshot folder
+    /// as one of the planned links, but under a different name, such as after an app's name has changed
+    pub fn remove_renamed_links(
+        &mut self,
+        steamid_str: &str,
+        target_screenshots_dir: &Path,
+        planned_links: &[PlannedLink],
+        manifest: &mut Manifest,
+    ) -> Result<()> {
+        for entry in target_screenshots_dir.read_dir()? {
+            let entry = entry?;
+            let path = entry.path();
+            let file_type = entry.file_type()?;
+
+            // Aliases are files, so where they point can only be found from the manifest
+            let target = if file_type.is_symlink() && manifest.owns_link(&path) {
+                std::fs::read_link(&path).ok()
+            } else if file_type.is_file() {
+                manifest
+                    .link(&path)
+                    .map(|link_record| link_record.source.clone())
+            } else {
+                None
+            };
+
+            let Some(target) = target else {
+                continue;
+            };
+
+            let Some(planned_link) = planned_links
+                .iter()
+                .find(|planned_link| link_points_to(&path, &target, &planned_link.source))
+            else {
+                continue;
+            };
+
+            if entry.file_name() == OsStr::new(&planned_link.name) {
+                continue;
+            }
+
+            Event::info(
+                "unlink",
+                format!(
+                    "Removing {:?}, as the app's folder is now named {:?}",
+                    path, planned_link.name
+                ),
+            )
+            .user(steamid_str)
+            .appid(planned_link.appid)
+            .path(&path)
+            .log();
+
+            let result = if file_type.is_symlink() {
+                self.operations.remove_symlink(&path)
+            } else {
+                self.operations.remove_alias(&path)
+            };
+
+            match result {
+                Ok(_) => {
+                    manifest.forget_link(&path);
+                    self.stats.removed += 1;
+                }
+                Err(error) => {
+                    Event::error("unlink", format!("Error unlinking {:?}: {}", path, error))
+                        .user(steamid_str)
+                        .appid(planned_link.appid)
+                        .path(&path)
+                        .log();
+                    self.stats.errors += 1;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Removes any app ID-named symlinks lnshot made in a user's folder for apps whose names are now known.
+    ///
+    /// Returns the app ID-named folders which lnshot didn't make as symlinks, and so were left alone,
+    /// along with the names they could have.
+    pub fn clean_up(
+        &mut self,
+        steamid_str: &str,
+        target_screenshots_dir: &Path,
+        app_name_resolver: &AppNameResolver,
+        manifest: &mut Manifest,
+    ) -> Result<Vec<(u64, OsString)>> {
+        let mut orphaned_folders = Vec::new();
+
+        for entry in target_screenshots_dir.read_dir()? {
+            let entry = entry?;
+            let filename = entry.file_name();
+
+            let appid_str = filename
+                .to_str()
+                .with_context(|| "Failed to retrieve an app id")?;
+
+            if let Ok(appid) = appid_str.parse::<u64>() {
+                Event::trace(
+                    "clean_up",
+                    format!("Cleanup found dir with app id: {}", appid),
+                )
+                .user(steamid_str)
+                .path(entry.path())
+                .log();
+
+                if let Some(app_name) = app_name_resolver.resolve(appid)? {
+                    let entry_symlink_path = entry.path();
+
+                    if entry_symlink_path.is_symlink() && manifest.owns_link(&entry_symlink_path) {
+                        Event::info(
+                            "clean_up",
+                            format!("App {} is installed! We don't need this symlink", appid),
+                        )
+                        .user(steamid_str)
+                        .path(&entry_symlink_path)
+                        .log();
+
+                        match self.operations.remove_symlink(&entry_symlink_path) {
+                            Ok(_) => {
+                                manifest.forget_link(&entry_symlink_path);
+                                self.stats.removed += 1;
+                            }
+                            Err(error) => {
+                                Event::error(
+                                    "clean_up",
+                                    format!("Error unlinking {:?}: {}", entry_symlink_path, error),
+                                )
+                                .user(steamid_str)
+                                .path(&entry_symlink_path)
+                                .log();
+                                self.stats.errors += 1;
+                            }
+                        };
+                    } else {
+                        Event::warn(
+                            "clean_up",
+                            format!(
+                                "App {} is installed, but the matching item is not a symlink lnshot made; skipping!",
+                                appid
+                            ),
+                        )
+                        .user(steamid_str)
+                        .path(&entry_symlink_path)
+                        .log();
+
+                        orphaned_folders.push((appid, app_name.to_owned()));
+                    }
+                }
+            }
+        }
+
+        orphaned_folders.sort();
+        Ok(orphaned_folders)
+    }
+
+    /// Removes every symlink and alias lnshot made inside the users' folders, undoing what lnshot has done.
+    /// Anything else, including copied screenshots, is left alone. If the manifest doesn't record
+    /// which links lnshot made, symlinks pointing into Steam's `userdata` directory are removed.
+    ///
+    /// If `remove_empty_folders` is set, empty placeholder folders are removed, along with any
+    /// user folders left empty. Only folders the manifest records lnshot making are removed,
+    /// so empty folders made by anyone else, or by versions of lnshot which didn't record them, are left alone.
+    /// Folders other than users' aren't looked inside at all, unless the manifest is too old to record users' folders.
+    ///
+    /// Returns the number of symlinks and aliases removed.
+    pub fn clean(
+        &mut self,
+        screenshots_dir: &Path,
+        steam_user_data_dir: &Path,
+        remove_empty_folders: bool,
+        manifest: &mut Manifest,
+    ) -> Result<usize> {
+        let canonical_user_data_dir = steam_user_data_dir.canonicalize().ok();
+        let mut removed_count = 0;
+
+        if !screenshots_dir.is_dir() {
+            return Ok(removed_count);
+        }
+
+        for user_entry in screenshots_dir.read_dir()? {
+            let user_entry = user_entry?;
+
+            if !user_entry.file_type()?.is_dir() {
+                continue;
+            }
+
+            // The managed directory may be shared with other things, such as the system's own screenshots folder,
+            // so once the manifest records users' folders, nothing else is looked inside
+            if manifest.has_user_folders() && !manifest.is_user_folder(&user_entry.file_name()) {
+                continue;
+            }
+
+            let mut remaining_count = 0;
+
+            for entry in user_entry.path().read_dir()? {
+                let entry = entry?;
+                let path = entry.path();
+                let file_type = entry.file_type()?;
+
+                if file_type.is_symlink() {
+                    let target = user_entry.path().join(std::fs::read_link(&path)?);
+
+                    let points_into_user_data = target.starts_with(steam_user_data_dir)
+                        || canonical_user_data_dir
+                            .as_ref()
+                            .is_some_and(|canonical_dir| target.starts_with(canonical_dir));
+
+                    let made_by_lnshot = if manifest.tracks_links() {
+                        manifest.owns_link(&path)
+                    } else {
+                        points_into_user_data
+                    };
+
+                    if made_by_lnshot {
+                        Event::info("clean", format!("Removing symlink {:?}", path))
+                            .path(&path)
+                            .log();
+
+                        match self.operations.remove_symlink(&path) {
+                            Ok(_) => {
+                                manifest.forget_link(&path);
+                                removed_count += 1;
+                                self.stats.removed += 1;
+                                continue;
+                            }
+                            Err(error) => {
+                                Event::error(
+                                    "clean",
+                                    format!("Error unlinking {:?}: {}", path, error),
+                                )
+                                .path(&path)
+                                .log();
+                                self.stats.errors += 1;
+                            }
+                        }
+                    }
+                } else if file_type.is_file() && manifest.link(&path).is_some() {
+                    Event::info("clean", format!("Removing alias {:?}", path))
+                        .path(&path)
+                        .log();
+
+                    match self.operations.remove_alias(&path) {
+                        Ok(_) => {
+                            manifest.forget_link(&path);
+                            removed_count += 1;
+                            self.stats.removed += 1;
+                            continue;
+                        }
+                        Err(error) => {
+                            Event::error("clean", format!("Error removing {:?}: {}", path, error))
+                                .path(&path)
+                                .log();
+                            self.stats.errors += 1;
+                        }
+                    }
+                } else if remove_empty_folders
+                    && file_type.is_dir()
+                    && manifest.is_placeholder(&path)
+                    && path.read_dir()?.next().is_none()
+                {
+                    Event::info(
+                        "clean",
+                        format!("Removing empty placeholder folder {:?}", path),
+                    )
+                    .path(&path)
+                    .log();
+
+                    match self.operations.remove_dir(&path) {
+                        Ok(_) => {
+                            manifest.forget_placeholder(&path);
+                            continue;
+                        }
+                        Err(error) => {
+                            Event::error("clean", format!("Error removing {:?}: {}", path, error))
+                                .path(&path)
+                                .log();
+                            self.stats.errors += 1;
+                        }
+                    }
+                }
+
+                remaining_count += 1;
+            }
+
+            if remove_empty_folders
+                && remaining_count == 0
+                && manifest.is_user_folder(&user_entry.file_name())
+            {
+                Event::info(
+                    "clean",
+                    format!("Removing empty user folder {:?}", user_entry.path()),
+                )
+                .path(user_entry.path())
+                .log();
+
+                if let Err(error) = self.operations.remove_dir(&user_entry.path()) {
+                    Event::error(
+                        "clean",
+                        format!("Error removing {:?}: {}", user_entry.path(), error),
+                    )
+                    .path(user_entry.path())
+                    .log();
+                    self.stats.errors += 1;
+                }
+            }
+        }
+
+        Ok(removed_count)
+    }
+}
+
+/// Reads where a symlink or junction points. Windows reports junctions' targets as extended-length paths,
+/// so that prefix is removed to match the paths lnshot links to.
+fn read_link_target(link_path: &Path) -> Option<PathBuf> {
+    let target = std::fs::read_link(link_path).ok()?;
+
+    if cfg!(windows) {
+        if let Some(target) = target
+            .to_str()
+            .and_then(|target| target.strip_prefix(r"\\?\"))
+        {
+            return Some(PathBuf::from(target));
+        }
+    }
+
+    Some(target)
+}
+
+/// Checks whether creating a symlink failed because the user isn't allowed to make them,
+/// as on Windows without Developer Mode or administrator rights
+fn is_privilege_error(error: &std::io::Error) -> bool {
+    // `ERROR_PRIVILEGE_NOT_HELD`
+    error.raw_os_error() == Some(1314) || error.kind() == std::io::ErrorKind::PermissionDenied
+}
+
+/// Checks whether a symlink with the given target points to `source`, whether its target is absolute or relative
+pub fn link_points_to(link_path: &Path, target: &Path, source: &Path) -> bool {
+    if target == source {
+        return true;
+    }
+
+    // Relative targets can only be compared once both paths are resolved to where they really are
+    target.is_relative()
+        && matches!(
+            (link_path.canonicalize(), source.canonicalize()),
+            (Ok(resolved_target), Ok(resolved_source)) if resolved_target == resolved_source
+        )
+}
+
+/// Finds the app ID-named folders in a user's folder which lnshot didn't make as symlinks, and so
+/// can't tidy up itself, but whose apps now have names. Nothing is changed.
+pub fn find_renameable_folders(
+    target_screenshots_dir: &Path,
+    app_name_resolver: &AppNameResolver,
+    manifest: &Manifest,
+) -> Result<Vec<(u64, OsString)>> {
+    let mut renameable_folders = Vec::new();
+
+    if !target_screenshots_dir.is_dir() {
+        return Ok(renameable_folders);
+    }
+
+    for entry in target_screenshots_dir.read_dir()? {
+        let entry = entry?;
+        let Some(appid) = entry
+            .file_name()
+            .to_str()
+            .and_then(|appid_str| appid_str.parse::<u64>().ok())
+        else {
+            continue;
+        };
+
+        let entry_path = entry.path();
+        if entry_path.is_symlink() && manifest.owns_link(&entry_path) {
+            continue;
+        }
+
+        if let Some(app_name) = app_name_resolver.resolve(appid)? {
+            renameable_folders.push((appid, app_name.to_owned()));
+        }
+    }
+
+    renameable_folders.sort();
+    Ok(renameable_folders)
+}
+
+/// Works out a relative path from a folder to another path, by way of the folder they have in common.
+/// Symlinks in either path are resolved first, as the relative path is followed from where the folder
+/// really is. Paths with nothing in common, such as on different drives, are returned as they are.
+fn relative_path(from_dir: &Path, to: &Path) -> PathBuf {
+    let from_dir = from_dir
+        .canonicalize()
+        .unwrap_or_else(|_| from_dir.to_path_buf());
+    let to_resolved = to.canonicalize().unwrap_or_else(|_| to.to_path_buf());
+
+    let from_components = from_dir.components().collect::<Vec<_>>();
+    let to_components = to_resolved.components().collect::<Vec<_>>();
+
+    let common_count = from_components
+        .iter()
+        .zip(&to_components)
+        .take_while(|(from_component, to_component)| from_component == to_component)
+        .count();
+
+    // Without a shared root, there's no way to get from one to the other
+    if common_count == 0 || !from_dir.has_root() {
+        return to.to_path_buf();
+    }
+
+    let mut relative_path = PathBuf::new();
+
+    for _ in common_count..from_components.len() {
+        relative_path.push("..");
+    }
+
+    for component in &to_components[common_count..] {
+        relative_path.push(component);
+    }
+
+    relative_path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_resolutions() {
+        assert_eq!(
+            "1920x1080".parse::<Resolution>().unwrap(),
+            Resolution {
+                width: 1920,
+                height: 1080
+            }
+        );
+        assert_eq!(
+            "1280X800".parse::<Resolution>().unwrap().to_string(),
+            "1280x800"
+        );
+        assert!("1920".parse::<Resolution>().is_err());
+        assert!("widexhigh".parse::<Resolution>().is_err());
+    }
+
+    #[test]
+    fn parses_file_sizes() {
+        assert_eq!("512".parse::<FileSize>().unwrap(), FileSize(512));
+        assert_eq!("200K".parse::<FileSize>().unwrap(), FileSize(200 * 1024));
+        assert_eq!("2m".parse::<FileSize>().unwrap().to_string(), "2M");
+        assert_eq!(FileSize(1500).to_string(), "1500");
+        assert!("big".parse::<FileSize>().is_err());
+    }
+}
@@ -0,0 +1,701 @@
+use crate::log::Event;
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use directories::BaseDirs;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use steamid_ng::{SteamID, Universe};
+use steamlocate::SteamDir;
+
+/// A Steam user who has logged into Steam on this computer
+#[derive(Debug, Clone)]
+pub struct SteamUser {
+    pub steamid: SteamID,
+
+    /// The user's SteamID64, as written in `loginusers.vdf`
+    pub steamid_str: String,
+
+    /// The user's display name
+    pub persona_name: String,
+
+    /// Whether this is the user who most recently logged into Steam
+    pub most_recent: bool,
+}
+
+impl SteamUser {
+    /// The user's account ID, which names their folder in Steam's `userdata` directory
+    pub fn account_id(&self) -> u64 {
+        u64::from(self.steamid.account_id())
+    }
+}
+
+/// Finds Steam users and their screenshot folders, using only what the Steam client has stored on disk
+pub struct ScreenshotLocator {
+    steam_path: PathBuf,
+}
+
+impl ScreenshotLocator {
+    /// Creates a locator for the Steam installation at the given path
+    pub fn new(steam_path: &Path) -> ScreenshotLocator {
+        ScreenshotLocator {
+            steam_path: steam_path.to_path_buf(),
+        }
+    }
+
+    /// Steam's `userdata` directory, which holds a folder for each user's account ID
+    pub fn user_data_dir(&self) -> PathBuf {
+        self.steam_path.join("userdata")
+    }
+
+    /// The folder holding a user's app screenshot folders
+    pub fn user_screenshots_dir(&self, account_id: u64) -> PathBuf {
+        self.user_data_dir()
+            .join(account_id.to_string())
+            .join("760")
+            .join("remote")
+    }
+
+    /// The folder Steam saves a user's screenshots of an app into
+    pub fn app_screenshots_dir(&self, account_id: u64, appid: u64) -> PathBuf {
+        self.user_screenshots_dir(account_id)
+            .join(appid.to_string())
+            .join("screenshots")
+    }
+
+    /// The file Steam lists the users who have logged in on this computer in
+    pub fn login_users_path(&self) -> PathBuf {
+        self.steam_path.join("config").join("loginusers.vdf")
+    }
+
+    /// Reads the users who have logged into Steam on this computer, in order of their SteamID64
+    pub fn users(&self) -> Result<Vec<SteamUser>> {
+        self.each_user()?.into_iter().collect()
+    }
+
+    /// Reads the users who have logged into Steam on this computer, like [`Self::users`],
+    /// but reads each on its own, so one malformed entry doesn't stop the rest from being read.
+    /// Entries which couldn't be read come first.
+    pub fn each_user(&self) -> Result<Vec<Result<SteamUser>>> {
+        let users_list = steamy_vdf::load(self.login_users_path())?
+            .get("users")
+            .with_context(|| "Failed to find any Steam users")?
+            .as_table()
+            .with_context(|| "Failed to find any Steam users")?
+            .to_owned();
+
+        let mut users = users_list
+            .iter()
+            .map(|(steamid_str, userinfo)| {
+                let persona_name = userinfo
+                    .get("PersonaName")
+                    .with_context(|| {
+                        format!("Failed to retrieve account PersonaName for {}", steamid_str)
+                    })?
+                    .as_str()
+                    .with_context(|| {
+                        format!(
+                            "Failed to convert PersonaName for {} into a string",
+                            steamid_str
+                        )
+                    })?;
+
+                // Older versions of Steam wrote this key in lowercase
+                let most_recent = userinfo.as_table().is_some_and(|userinfo| {
+                    userinfo.iter().any(|(key, value)| {
+                        key.eq_ignore_ascii_case("MostRecent") && value.as_str() == Some("1")
+                    })
+                });
+
+                let steamid = steamid_str
+                    .parse::<u64>()
+                    .with_context(|| format!("Failed to read SteamID64 {:?}", steamid_str))?;
+
+                Ok(SteamUser {
+                    steamid: SteamID::from(steamid),
+                    steamid_str: steamid_str.clone(),
+                    persona_name: persona_name.to_string(),
+                    most_recent,
+                })
+            })
+            .collect::<Vec<Result<_>>>();
+
+        users.sort_by_key(|user| user.as_ref().ok().map(|user| u64::from(user.steamid)));
+        Ok(users)
+    }
+
+    /// Finds the app IDs of a user's screenshot folders.
+    ///
+    /// They're returned in order of their folder names, so any clashing names
+    /// are disambiguated the same way each time.
+    pub fn app_screenshot_folders(&self, account_id: u64) -> Result<Vec<u64>> {
+        self.each_app_screenshot_folder(account_id)?
+            .into_iter()
+            .collect()
+    }
+
+    /// Finds the app IDs of a user's screenshot folders, like [`Self::app_screenshot_folders`],
+    /// but reads each folder's name on its own, so one which isn't an app ID doesn't stop the rest from being found
+    pub fn each_app_screenshot_folder(&self, account_id: u64) -> Result<Vec<Result<u64>>> {
+        let user_screenshots_dir = self.user_screenshots_dir(account_id);
+        let mut entries = user_screenshots_dir
+            .read_dir()
+            .and_then(|entries| entries.collect::<std::io::Result<Vec<_>>>())
+            .with_context(|| format!("Failed to read {:?}", user_screenshots_dir))?;
+        entries.sort_by_key(|entry| entry.file_name());
+
+        let mut appids = Vec::new();
+
+        for entry in entries {
+            if !entry.path().is_dir() {
+                continue;
+            }
+
+            let filename = entry.file_name();
+
+            appids.push(
+                filename
+                    .to_str()
+                    .and_then(|appid_str| appid_str.parse::<u64>().ok())
+                    .with_context(|| {
+                        format!(
+                            "Screenshot folder {:?} isn't named after an app ID",
+                            entry.path()
+                        )
+                    }),
+            );
+        }
+
+        Ok(appids)
+    }
+
+    /// Counts the screenshots a user has taken of an app. Thumbnails aren't counted.
+    pub fn count_screenshots(&self, account_id: u64, appid: u64) -> Result<usize> {
+        let app_screenshots_dir = self.app_screenshots_dir(account_id, appid);
+
+        if !app_screenshots_dir.is_dir() {
+            return Ok(0);
+        }
+
+        let mut count = 0;
+
+        for entry in app_screenshots_dir.read_dir()? {
+            if entry?.file_type()?.is_file() {
+                count += 1;
+            }
+        }
+
+        Ok(count)
+    }
+
+    /// Finds every app screenshot folder in Steam's `userdata` directory,
+    /// as pairs of the owning user's account ID and the app ID.
+    pub fn find_screenshot_folders(&self) -> Result<Vec<(u64, u64)>> {
+        let mut screenshot_folders = Vec::new();
+
+        for user_entry in self.user_data_dir().read_dir()? {
+            let user_entry = user_entry?;
+
+            let Some(account_id) = user_entry
+                .file_name()
+                .to_str()
+                .and_then(|account_id_str| account_id_str.parse::<u64>().ok())
+            else {
+                continue;
+            };
+
+            let steam_user_screenshots_dir = user_entry.path().join("760").join("remote");
+            if !steam_user_screenshots_dir.is_dir() {
+                continue;
+            }
+
+            // Folders which can't be read are reported when their user is processed
+            let Ok(app_entries) = steam_user_screenshots_dir.read_dir() else {
+                continue;
+            };
+
+            for app_entry in app_entries.flatten() {
+                if let Some(appid) = app_entry
+                    .file_name()
+                    .to_str()
+                    .and_then(|appid_str| appid_str.parse::<u64>().ok())
+                {
+                    screenshot_folders.push((account_id, appid));
+                }
+            }
+        }
+
+        screenshot_folders.sort();
+        Ok(screenshot_folders)
+    }
+}
+
+/// Folders Steam may be installed in, along with how Steam was installed there.
+/// `SteamDir::locate` only checks some of these, and only returns the first it finds.
+fn known_steam_paths() -> Vec<(&'static str, PathBuf)> {
+    if !cfg!(target_os = "linux") {
+        return Vec::new();
+    }
+
+    let Some(base_dirs) = BaseDirs::new() else {
+        return Vec::new();
+    };
+
+    // Flatpak and Snap keep Steam's data inside their own sandboxed home folders
+    let flatpak_dir = base_dirs
+        .home_dir()
+        .join(".var/app/com.valvesoftware.Steam");
+    let snap_dir = base_dirs.home_dir().join("snap/steam/common");
+
+    vec![
+        ("Native", base_dirs.home_dir().join(".steam/steam")),
+        ("Native", base_dirs.home_dir().join(".local/share/Steam")),
+        ("Flatpak", flatpak_dir.join("data/Steam")),
+        ("Flatpak", flatpak_dir.join(".local/share/Steam")),
+        ("Flatpak", flatpak_dir.join(".steam/steam")),
+        ("Snap", snap_dir.join(".local/share/Steam")),
+        ("Snap", snap_dir.join(".steam/steam")),
+    ]
+}
+
+/// Creates a `SteamDir` for the Steam installation at the given path
+fn steam_dir_at(steam_path: PathBuf) -> SteamDir {
+    let mut steam_dir = SteamDir::default();
+    steam_dir.path = steam_path;
+    steam_dir
+}
+
+/// Finds every Steam installation on this computer, named after how each was installed,
+/// such as `Native` or `Flatpak`. Installations reachable through several paths are only found once.
+pub fn locate_all_steam() -> Vec<(String, SteamDir)> {
+    let mut candidates = known_steam_paths();
+
+    if let Some(steam_dir) = SteamDir::locate() {
+        candidates.push(("Steam", steam_dir.path));
+    }
+
+    let mut installations: Vec<(String, SteamDir)> = Vec::new();
+    let mut seen_paths = Vec::new();
+
+    for (kind, steam_path) in candidates {
+        if !steam_path.join("userdata").is_dir() {
+            continue;
+        }
+
+        // Steam's own `~/.steam/steam` symlink points at one of the other folders
+        let canonical_path = steam_path
+            .canonicalize()
+            .unwrap_or_else(|_| steam_path.clone());
+
+        if seen_paths.contains(&canonical_path) {
+            continue;
+        }
+
+        seen_paths.push(canonical_path);
+
+        let same_kind_count = installations
+            .iter()
+            .filter(|(name, _)| name == kind || name.starts_with(&format!("{} ", kind)))
+            .count();
+
+        let name = match same_kind_count {
+            0 => kind.to_string(),
+            _ => format!("{} {}", kind, same_kind_count + 1),
+        };
+
+        installations.push((name, steam_dir_at(steam_path)));
+    }
+
+    installations
+}
+
+/// What to do when several Steam installations have a screenshot folder for the same user and app
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DuplicatePolicy {
+    /// Link only the folder which was modified most recently
+    Newest,
+
+    /// Link every folder, adding the installation's name to all but the first one's folder name,
+    /// such as `Portal 2 (Flatpak)`
+    Both,
+}
+
+/// How an installation treats an app's screenshot folder which another installation also has for the same user
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DuplicateFolder {
+    /// Another installation's folder is linked instead
+    Skip,
+
+    /// The folder is linked too, with this installation's name added to its folder name
+    Distinguish(String),
+}
+
+/// Finds app screenshot folders which several installations have for the same user, and works out
+/// how each installation should treat them under the policy. Each duplicate is logged as it's found.
+///
+/// Returns the treatment for each installation, in the same order, keyed by account ID and app ID.
+pub fn find_duplicate_folders(
+    installations: &[(String, SteamDir)],
+    policy: DuplicatePolicy,
+) -> Result<Vec<HashMap<(u64, u64), DuplicateFolder>>> {
+    let mut found = BTreeMap::<(u64, u64), Vec<usize>>::new();
+
+    for (index, (_installation_name, steam_dir)) in installations.iter().enumerate() {
+        for folder in ScreenshotLocator::new(&steam_dir.path).find_screenshot_folders()? {
+            found.entry(folder).or_default().push(index);
+        }
+    }
+
+    let mut duplicate_folders = vec![HashMap::new(); installations.len()];
+
+    for ((account_id, appid), indices) in found {
+        if indices.len() < 2 {
+            continue;
+        }
+
+        let names = indices
+            .iter()
+            .map(|index| installations[*index].0.as_str())
+            .collect::<Vec<_>>();
+
+        Event::info(
+            "duplicate",
+            format!(
+                "Found screenshot folders for account {} in the {} installations",
+                account_id,
+                names.join(" and ")
+            ),
+        )
+        .appid(appid)
+        .log();
+
+        // Ties go to the installation found first, as with the folder whose name is left as is
+        let kept_index = match policy {
+            DuplicatePolicy::Newest => indices
+                .iter()
+                .copied()
+                .rev()
+                .max_by_key(|index| {
+                    ScreenshotLocator::new(&installations[*index].1.path)
+                        .app_screenshots_dir(account_id, appid)
+                        .metadata()
+                        .and_then(|metadata| metadata.modified())
+                        .ok()
+                })
+                .expect("duplicates are found in at least two installations"),
+            DuplicatePolicy::Both => indices[0],
+        };
+
+        for index in indices {
+            if index == kept_index {
+                continue;
+            }
+
+            let duplicate_folder = match policy {
+                DuplicatePolicy::Newest => DuplicateFolder::Skip,
+                DuplicatePolicy::Both => {
+                    DuplicateFolder::Distinguish(installations[index].0.clone())
+                }
+            };
+
+            duplicate_folders[index].insert((account_id, appid), duplicate_folder);
+        }
+    }
+
+    Ok(duplicate_folders)
+}
+
+/// Finds the Steam installation, either at the given path or wherever Steam is installed by default
+pub fn locate_steam(steam_path: Option<&Path>) -> Result<SteamDir> {
+    let steam_path = match steam_path {
+        Some(steam_path) => {
+            if !steam_path.is_dir() {
+                anyhow::bail!("Steam path {:?} isn't a folder", steam_path);
+            }
+
+            // Steam creates its `userdata` folder on first login, and it's the only folder lnshot needs
+            if !steam_path.join("userdata").is_dir() {
+                anyhow::bail!(
+                    "Steam path {:?} doesn't look like a Steam installation, as it has no userdata folder",
+                    steam_path
+                );
+            }
+
+            steam_path.to_path_buf()
+        }
+        None => {
+            if let Some(steam_dir) = SteamDir::locate() {
+                return Ok(steam_dir);
+            }
+
+            known_steam_paths()
+                .into_iter()
+                .map(|(_kind, steam_path)| steam_path)
+                .find(|steam_path| steam_path.join("userdata").is_dir())
+                .with_context(|| "Failed to locate Steam on this computer")?
+        }
+    };
+
+    Ok(steam_dir_at(steam_path))
+}
+
+/// The users who have logged into Steam on this computer, kept between reads of `loginusers.vdf`
+/// so it's only read again once it has changed, such as by the daemon
+pub struct CachedUsers {
+    users: Vec<SteamUser>,
+
+    /// When the file was last changed, and its size, when it was read
+    read_from: Option<(SystemTime, u64)>,
+}
+
+impl CachedUsers {
+    pub fn load(locator: &ScreenshotLocator) -> Result<CachedUsers> {
+        let read_from = file_version(&locator.login_users_path());
+
+        Ok(CachedUsers {
+            users: locator.users()?,
+            read_from,
+        })
+    }
+
+    pub fn users(&self) -> &[SteamUser] {
+        &self.users
+    }
+
+    /// Reads the users again if `loginusers.vdf` has changed since it was last read.
+    /// If it can't be read, such as while Steam is writing it, the users already read are kept.
+    ///
+    /// Returns whether the users were read again.
+    pub fn refresh(&mut self, locator: &ScreenshotLocator) -> Result<bool> {
+        let read_from = file_version(&locator.login_users_path());
+
+        if read_from.is_some() && read_from == self.read_from {
+            return Ok(false);
+        }
+
+        self.users = locator.users()?;
+        self.read_from = read_from;
+        Ok(true)
+    }
+}
+
+/// When a file was last changed, and its size, to tell whether it has changed since
+fn file_version(path: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = path.metadata().ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// Works out which user's screenshot folder, and which app's, a changed path is in.
+/// Returns the path relative to the user data directory, along with the account ID and app ID.
+///
+/// Changes are reported under the directory being watched, which may be the real location of a relocated
+/// user data directory rather than the path Steam uses, so paths under either are understood.
+pub fn changed_app(
+    path: &Path,
+    watch_dir: &Path,
+    user_data_dir: &Path,
+) -> Result<(PathBuf, u64, u64)> {
+    let relative_path = path
+        .strip_prefix(watch_dir)
+        .or_else(|_| path.strip_prefix(user_data_dir))
+        .with_context(|| {
+            format!(
+                "Changed path {:?} is outside both {:?} and {:?}",
+                path, watch_dir, user_data_dir
+            )
+        })?
+        .to_path_buf();
+
+    let mut path_components = relative_path
+        .components()
+        .filter_map(|component| match component {
+            std::path::Component::Normal(name) => Some(name),
+            _ => None,
+        });
+
+    // Screenshots are in `<account ID>/760/remote/<app ID>/screenshots`
+    let account_id = path_components
+        .next()
+        .and_then(|component| component.to_str())
+        .with_context(|| "Unable to find required user ID component")?
+        .parse::<u64>()?;
+    let appid = path_components
+        .nth(2)
+        .and_then(|component| component.to_str())
+        .with_context(|| "Unable to find required app ID component")?
+        .parse::<u64>()?;
+
+    Ok((relative_path, account_id, appid))
+}
+
+/// Finds the user with the given account ID.
+///
+/// Account IDs are only unique within a Steam universe, so if several users share one,
+/// the user in the public universe is preferred, as that's the one the Steam client uses.
+pub fn find_user_by_account_id(users: &[SteamUser], account_id: u64) -> Option<&SteamUser> {
+    let mut candidates = users
+        .iter()
+        .filter(|user| user.account_id() == account_id)
+        .collect::<Vec<_>>();
+
+    if candidates.len() > 1 {
+        Event::warn(
+            "users",
+            format!(
+                "Found {} users sharing this account ID; preferring the public universe",
+                candidates.len()
+            ),
+        )
+        .user(account_id)
+        .log();
+
+        candidates.sort_by_key(|user| {
+            (
+                user.steamid.universe() != Universe::Public,
+                u64::from(user.steamid),
+            )
+        });
+    }
+
+    candidates.into_iter().next()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use steamid_ng::{AccountType, Instance};
+
+    fn user(
+        account_id: u32,
+        instance: Instance,
+        universe: Universe,
+        persona_name: &str,
+    ) -> SteamUser {
+        let steamid = SteamID::new(account_id, instance, AccountType::Individual, universe);
+
+        SteamUser {
+            steamid,
+            steamid_str: u64::from(steamid).to_string(),
+            persona_name: persona_name.to_string(),
+            most_recent: false,
+        }
+    }
+
+    #[test]
+    fn finds_users_by_account_id() {
+        let users = [
+            user(1234, Instance::Desktop, Universe::Public, "Tester"),
+            user(5678, Instance::Desktop, Universe::Public, "Second"),
+        ];
+
+        assert_eq!(
+            find_user_by_account_id(&users, 5678).map(|user| user.persona_name.as_str()),
+            Some("Second")
+        );
+        assert!(find_user_by_account_id(&users, 9012).is_none());
+    }
+
+    #[test]
+    fn prefers_the_public_universe_for_shared_account_ids() {
+        // Whichever order they're found in, the public universe's user is the one the Steam client uses
+        let mut users = vec![
+            user(1234, Instance::Desktop, Universe::Beta, "Beta"),
+            user(1234, Instance::Desktop, Universe::Public, "Public"),
+        ];
+
+        assert_eq!(
+            find_user_by_account_id(&users, 1234).map(|user| user.persona_name.as_str()),
+            Some("Public")
+        );
+
+        users.reverse();
+        assert_eq!(
+            find_user_by_account_id(&users, 1234).map(|user| user.persona_name.as_str()),
+            Some("Public")
+        );
+    }
+
+    #[test]
+    fn prefers_the_lowest_steamid_for_shared_account_ids_in_one_universe() {
+        let users = [
+            user(1234, Instance::Console, Universe::Public, "Console"),
+            user(1234, Instance::Desktop, Universe::Public, "Desktop"),
+        ];
+
+        assert_eq!(
+            find_user_by_account_id(&users, 1234).map(|user| user.persona_name.as_str()),
+            Some("Desktop")
+        );
+    }
+
+    #[test]
+    fn finds_apps_of_changed_paths() {
+        let user_data_dir = Path::new("/home/deck/.steam/steam/userdata");
+
+        assert_eq!(
+            changed_app(
+                &user_data_dir.join("1234/760/remote/620/screenshots/20240101_1.jpg"),
+                user_data_dir,
+                user_data_dir,
+            )
+            .unwrap(),
+            (
+                PathBuf::from("1234/760/remote/620/screenshots/20240101_1.jpg"),
+                1234,
+                620
+            )
+        );
+    }
+
+    #[test]
+    fn finds_apps_of_changed_paths_in_relocated_user_data() {
+        // `userdata` is a symlink to a folder on another drive, where its changes are reported
+        let user_data_dir = Path::new("/home/deck/.steam/steam/userdata");
+        let watch_dir = Path::new("/run/media/mmcblk0p1/userdata");
+
+        assert_eq!(
+            changed_app(
+                &watch_dir.join("1234/760/remote/620/screenshots/20240101_1.jpg"),
+                watch_dir,
+                user_data_dir,
+            )
+            .unwrap(),
+            (
+                PathBuf::from("1234/760/remote/620/screenshots/20240101_1.jpg"),
+                1234,
+                620
+            )
+        );
+
+        // Some changes may still be reported against the path Steam uses
+        assert_eq!(
+            changed_app(
+                &user_data_dir.join("5678/760/remote/220"),
+                watch_dir,
+                user_data_dir,
+            )
+            .unwrap(),
+            (PathBuf::from("5678/760/remote/220"), 5678, 220)
+        );
+    }
+
+    #[test]
+    fn rejects_changed_paths_outside_user_data() {
+        let user_data_dir = Path::new("/home/deck/.steam/steam/userdata");
+        let watch_dir = Path::new("/run/media/mmcblk0p1/userdata");
+
+        assert!(changed_app(
+            Path::new("/home/deck/Pictures/1234/760/remote/620"),
+            watch_dir,
+            user_data_dir,
+        )
+        .is_err());
+        assert!(changed_app(&watch_dir.join("1234/760"), watch_dir, user_data_dir).is_err());
+        assert!(changed_app(
+            &watch_dir.join("config/760/remote/620"),
+            watch_dir,
+            user_data_dir
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,859 @@
+//! Logging what lnshot does through `tracing`, written either as lines of text or as one JSON object per line
+
+use anyhow::{Context as _, Result};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::cell::RefCell;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id};
+use tracing::subscriber::Interest;
+use tracing::{Metadata, Subscriber};
+use tracing_subscriber::layer::{Context as LayerContext, Layer, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+
+/// How log lines are written
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// Plain text, such as `[12345678; 220] Removing "..."`
+    #[default]
+    Text,
+
+    /// One JSON object per line, with the level, user, app ID, action and paths as fields of their own
+    Json,
+}
+
+static FORMAT: AtomicU8 = AtomicU8::new(LogFormat::Text as u8);
+
+/// Chooses how everything logged from now on is written
+pub fn set_format(format: LogFormat) {
+    FORMAT.store(format as u8, Ordering::Relaxed);
+}
+
+/// How log lines are being written
+pub fn format() -> LogFormat {
+    if FORMAT.load(Ordering::Relaxed) == LogFormat::Json as u8 {
+        LogFormat::Json
+    } else {
+        LogFormat::Text
+    }
+}
+
+static TO_STDERR: AtomicBool = AtomicBool::new(false);
+
+/// Writes everything logged from now on to standard error rather than standard output,
+/// when there's no log file, so standard output holds nothing but results for other tools to read
+pub fn log_to_stderr() {
+    TO_STDERR.store(true, Ordering::Relaxed);
+}
+
+static MAX_LEVEL: AtomicU8 = AtomicU8::new(Level::Info as u8);
+
+/// Chooses how much is logged from now on, from `-v` and `-q` given on the command line.
+/// Usually, everything but debugging detail is logged; each `-v` logs more, and `-q` logs only errors.
+pub fn set_verbosity(verbose: u8, quiet: bool) {
+    let level = if quiet {
+        Level::Error as u8
+    } else {
+        (Level::Info as u8 + verbose).min(Level::Trace as u8)
+    };
+    MAX_LEVEL.store(level, Ordering::Relaxed);
+}
+
+/// How many logs set aside by rotation are kept, besides the one being written
+const KEPT_LOGS: usize = 5;
+
+/// When the log file is set aside for a new one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogRotation {
+    /// Once it would grow past this many bytes. Older logs are numbered, such as `lnshot.log.1`.
+    Size(u64),
+
+    /// Once a day, at midnight UTC. Older logs are named after their day, such as `lnshot.log.2024-03-09`.
+    Daily,
+
+    Never,
+}
+
+impl Default for LogRotation {
+    fn default() -> Self {
+        LogRotation::Size(10 * 1024 * 1024)
+    }
+}
+
+impl std::str::FromStr for LogRotation {
+    type Err = anyhow::Error;
+
+    /// Reads `daily`, `never`, or a size in bytes, which may end with `K`, `M` or `G`, such as `10M`
+    fn from_str(value: &str) -> Result<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "daily" => return Ok(LogRotation::Daily),
+            "never" => return Ok(LogRotation::Never),
+            _ => {}
+        }
+
+        let (number, multiplier) = match value.char_indices().last() {
+            Some((index, 'k' | 'K')) => (&value[..index], 1024),
+            Some((index, 'm' | 'M')) => (&value[..index], 1024 * 1024),
+            Some((index, 'g' | 'G')) => (&value[..index], 1024 * 1024 * 1024),
+            _ => (value, 1),
+        };
+
+        match number.trim().parse::<u64>() {
+            Ok(size) if size > 0 => Ok(LogRotation::Size(size.saturating_mul(multiplier))),
+            _ => anyhow::bail!(
+                "Log rotation {:?} should be \"daily\", \"never\", or a size such as \"10M\"",
+                value
+            ),
+        }
+    }
+}
+
+impl std::fmt::Display for LogRotation {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            LogRotation::Size(size) if size % (1024 * 1024) == 0 => {
+                write!(formatter, "{}M", size / (1024 * 1024))
+            }
+            LogRotation::Size(size) if size % 1024 == 0 => write!(formatter, "{}K", size / 1024),
+            LogRotation::Size(size) => write!(formatter, "{}", size),
+            LogRotation::Daily => write!(formatter, "daily"),
+            LogRotation::Never => write!(formatter, "never"),
+        }
+    }
+}
+
+static LOG_FILE: Mutex<Option<LogFile>> = Mutex::new(None);
+
+/// Writes everything logged from now on to the file at this path, rather than standard output,
+/// setting it aside and starting a new one as the rotation says
+pub fn log_to_file(path: &Path, rotation: LogRotation) -> Result<()> {
+    if let Some(log_dir) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        std::fs::create_dir_all(log_dir)
+            .with_context(|| format!("Failed to create directory {:?}", log_dir))?;
+    }
+
+    let log_file = LogFile::open(path.to_path_buf(), rotation)
+        .with_context(|| format!("Failed to open log file {:?}", path))?;
+
+    *LOG_FILE.lock().unwrap_or_else(|error| error.into_inner()) = Some(log_file);
+    Ok(())
+}
+
+/// The file being logged to, along with what's needed to know when to rotate it
+struct LogFile {
+    path: PathBuf,
+    rotation: LogRotation,
+    file: File,
+    size: u64,
+
+    /// The day the file was started, in days since the Unix epoch
+    day: u64,
+}
+
+impl LogFile {
+    fn open(path: PathBuf, rotation: LogRotation) -> std::io::Result<LogFile> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let metadata = file.metadata()?;
+
+        // A log left from an earlier day is rotated as soon as anything is written to it
+        let day = unix_day(metadata.modified().unwrap_or_else(|_| SystemTime::now()));
+
+        Ok(LogFile {
+            path,
+            rotation,
+            file,
+            size: metadata.len(),
+            day,
+        })
+    }
+
+    fn write_line(&mut self, line: &str) -> std::io::Result<()> {
+        let length = line.len() as u64 + 1;
+
+        let rotate = match self.rotation {
+            LogRotation::Size(limit) => self.size > 0 && self.size + length > limit,
+            LogRotation::Daily => self.size > 0 && unix_day(SystemTime::now()) != self.day,
+            LogRotation::Never => false,
+        };
+
+        if rotate {
+            self.rotate()?;
+        }
+
+        writeln!(self.file, "{}", line)?;
+        self.size += length;
+        Ok(())
+    }
+
+    /// Sets the current file aside, removing the oldest ones beyond those kept, and starts a new one
+    fn rotate(&mut self) -> std::io::Result<()> {
+        match self.rotation {
+            LogRotation::Size(_) => {
+                let _ = std::fs::remove_file(self.rotated_path(KEPT_LOGS));
+
+                for number in (1..KEPT_LOGS).rev() {
+                    let from = self.rotated_path(number);
+                    if from.exists() {
+                        std::fs::rename(&from, self.rotated_path(number + 1))?;
+                    }
+                }
+
+                std::fs::rename(&self.path, self.rotated_path(1))?;
+            }
+            LogRotation::Daily => {
+                let (year, month, day) = civil_date(self.day);
+                let date = format!("{:04}-{:02}-{:02}", year, month, day);
+                std::fs::rename(&self.path, self.rotated_path(date))?;
+
+                self.remove_old_daily_logs()?;
+            }
+            LogRotation::Never => return Ok(()),
+        }
+
+        *self = LogFile::open(self.path.clone(), self.rotation)?;
+        self.day = unix_day(SystemTime::now());
+        Ok(())
+    }
+
+    /// The path of a log set aside by rotation, such as `lnshot.log.1`
+    fn rotated_path(&self, suffix: impl std::fmt::Display) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", suffix));
+        PathBuf::from(path)
+    }
+
+    /// Removes daily logs beyond those kept. Their dates sort in the order they were written.
+    fn remove_old_daily_logs(&self) -> std::io::Result<()> {
+        let (Some(log_dir), Some(file_name)) = (self.path.parent(), self.path.file_name()) else {
+            return Ok(());
+        };
+        let log_dir = if log_dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            log_dir
+        };
+        let prefix = format!("{}.", file_name.to_string_lossy());
+
+        let mut dated_logs = std::fs::read_dir(log_dir)?
+            .flatten()
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .filter(|name| {
+                name.strip_prefix(&prefix).is_some_and(|date| {
+                    date.len() == 10
+                        && date
+                            .chars()
+                            .all(|character| character.is_ascii_digit() || character == '-')
+                })
+            })
+            .collect::<Vec<_>>();
+        dated_logs.sort();
+
+        for name in dated_logs.iter().rev().skip(KEPT_LOGS) {
+            std::fs::remove_file(log_dir.join(name))?;
+        }
+
+        Ok(())
+    }
+}
+
+fn unix_day(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+        / 86400
+}
+
+/// How serious an event is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+
+    /// Detail about what lnshot found along the way, logged with `-v`
+    Debug,
+
+    /// Every step lnshot takes, logged with `-vv`
+    Trace,
+}
+
+impl Level {
+    fn name(self) -> &'static str {
+        match self {
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Info => "info",
+            Level::Debug => "debug",
+            Level::Trace => "trace",
+        }
+    }
+
+    fn from_tracing(level: tracing::Level) -> Level {
+        match level {
+            tracing::Level::ERROR => Level::Error,
+            tracing::Level::WARN => Level::Warn,
+            tracing::Level::INFO => Level::Info,
+            tracing::Level::DEBUG => Level::Debug,
+            tracing::Level::TRACE => Level::Trace,
+        }
+    }
+
+    /// Whether events of this level are being logged
+    pub fn is_enabled(self) -> bool {
+        self as u8 <= MAX_LEVEL.load(Ordering::Relaxed)
+    }
+}
+
+/// The `tracing` target lnshot's spans and events are sent with, so those of the crates it uses are left out
+const TARGET: &str = "lnshot";
+
+/// Sends everything lnshot logs from now on through [`LogLayer`], written in the chosen format.
+/// Until it's called, nothing is logged.
+pub fn init() {
+    // Only one subscriber can be set, and any set already writes the same way
+    let _ = tracing::subscriber::set_global_default(tracing_subscriber::registry().with(LogLayer));
+}
+
+/// The `tracing` layer which writes lnshot's events as lines of text or JSON,
+/// to the log file or standard output, taking the user and app from the spans they're inside
+struct LogLayer;
+
+impl<S> Layer<S> for LogLayer
+where
+    S: Subscriber + for<'lookup> LookupSpan<'lookup>,
+{
+    fn register_callsite(&self, metadata: &'static Metadata<'static>) -> Interest {
+        // How much is logged can change as lnshot runs, so each event is checked as it happens
+        if metadata.target().starts_with(TARGET) {
+            Interest::sometimes()
+        } else {
+            Interest::never()
+        }
+    }
+
+    fn enabled(&self, metadata: &Metadata<'_>, _context: LayerContext<'_, S>) -> bool {
+        // Spans are always kept, so errors logged inside them still say which user and app they're about
+        metadata.target().starts_with(TARGET)
+            && (metadata.is_span() || Level::from_tracing(*metadata.level()).is_enabled())
+    }
+
+    fn on_new_span(&self, attributes: &Attributes<'_>, id: &Id, context: LayerContext<'_, S>) {
+        let mut fields = Fields::default();
+        attributes.record(&mut fields);
+
+        if let Some(span) = context.span(id) {
+            span.extensions_mut().insert(fields);
+        }
+    }
+
+    fn on_event(&self, event: &tracing::Event<'_>, context: LayerContext<'_, S>) {
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+
+        // The innermost user span is who the event is about, and only app spans inside it count,
+        // so a user's span entered inside an app's span isn't about that app
+        let mut span_user = None;
+        let mut span_appid = None;
+        for span in context.event_scope(event).into_iter().flatten() {
+            let extensions = span.extensions();
+            let Some(span_fields) = extensions.get::<Fields>() else {
+                continue;
+            };
+
+            if span_user.is_some() {
+                break;
+            }
+
+            if span_appid.is_none() {
+                span_appid = span_fields.appid;
+            }
+
+            span_user.clone_from(&span_fields.user);
+        }
+
+        let line = Line {
+            level: Level::from_tracing(*event.metadata().level()),
+            action: fields.action.as_deref().unwrap_or_default(),
+            message: &fields.message,
+            user: fields.user.as_deref().or(span_user.as_deref()),
+            appid: fields.appid.or(span_appid),
+            paths: &fields.paths,
+        }
+        .render(format());
+        let time = SystemTime::now();
+
+        let line = BUFFER.with(|buffer| match buffer.borrow_mut().as_mut() {
+            Some(lines) => {
+                lines.push((time, line));
+                None
+            }
+            None => Some(line),
+        });
+
+        if let Some(line) = line {
+            write_line(time, line);
+        }
+    }
+}
+
+/// The fields of an event, or of a user's or app's span
+#[derive(Default)]
+struct Fields {
+    action: Option<String>,
+    message: String,
+    user: Option<String>,
+    appid: Option<u64>,
+    paths: Vec<String>,
+}
+
+impl Visit for Fields {
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        if field.name() == "appid" {
+            self.appid = Some(value);
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "action" => self.action = Some(value.to_string()),
+            "user" => self.user = Some(value.to_string()),
+            "message" => self.message = value.to_string(),
+            // Fields can't hold lists, so the paths are sent as a JSON array
+            "paths" => self.paths = serde_json::from_str(value).unwrap_or_default(),
+            _ => {}
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        match field.name() {
+            "user" => self.user = Some(format!("{:?}", value)),
+            "message" => self.message = format!("{:?}", value),
+            _ => {}
+        }
+    }
+}
+
+/// While held, everything logged on this thread which doesn't say otherwise is about this user or app.
+/// Spans nest, so an app's span inside a user's span covers both.
+#[must_use = "spans end as soon as they're dropped"]
+pub struct Span {
+    _entered: tracing::span::EnteredSpan,
+}
+
+impl Span {
+    /// Enters a span for a user, by their account ID or name
+    pub fn user(user: impl ToString) -> Span {
+        let span = tracing::span!(
+            target: TARGET,
+            tracing::Level::INFO,
+            "user",
+            user = user.to_string()
+        );
+
+        Span {
+            _entered: span.entered(),
+        }
+    }
+
+    /// Enters a span for an app
+    pub fn app(appid: u64) -> Span {
+        Span {
+            _entered: tracing::span!(target: TARGET, tracing::Level::INFO, "app", appid).entered(),
+        }
+    }
+}
+
+thread_local! {
+    static BUFFER: RefCell<Option<Vec<(SystemTime, String)>>> = const { RefCell::new(None) };
+}
+
+/// While held, everything logged on this thread is kept back, to be written all together later,
+/// so what threads working side by side log isn't mixed together.
+/// Buffers nest, so a buffer started inside another goes back to it once it ends.
+/// Anything still kept back when it's dropped is written straight away, or kept back by the outer buffer.
+#[must_use = "buffers end as soon as they're dropped"]
+pub struct Buffer {
+    /// What the buffer this one was started inside had kept back, until this one ends
+    outer: Option<Option<Vec<(SystemTime, String)>>>,
+}
+
+impl Buffer {
+    pub fn start() -> Buffer {
+        let outer = BUFFER.with(|buffer| buffer.borrow_mut().replace(Vec::new()));
+        Buffer { outer: Some(outer) }
+    }
+
+    /// Stops keeping back what's logged on this thread, returning what was kept so far
+    pub fn finish(mut self) -> BufferedLines {
+        BufferedLines(self.end().unwrap_or_default())
+    }
+
+    /// Goes back to the outer buffer, if there is one, returning what this one kept back
+    fn end(&mut self) -> Option<Vec<(SystemTime, String)>> {
+        let outer = self.outer.take()?;
+        BUFFER.with(|buffer| std::mem::replace(&mut *buffer.borrow_mut(), outer))
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        if let Some(lines) = self.end() {
+            BufferedLines(lines).write();
+        }
+    }
+}
+
+/// Lines kept back by a [`Buffer`], which can be sent to another thread to write
+pub struct BufferedLines(Vec<(SystemTime, String)>);
+
+impl BufferedLines {
+    /// Writes the lines, in the order they were logged, with the times they were logged.
+    /// Inside a buffer, they're kept back along with everything else it has.
+    pub fn write(self) {
+        let lines = BUFFER.with(|buffer| match buffer.borrow_mut().as_mut() {
+            Some(kept_lines) => {
+                kept_lines.extend(self.0);
+                None
+            }
+            None => Some(self.0),
+        });
+
+        for (time, line) in lines.into_iter().flatten() {
+            write_line(time, line);
+        }
+    }
+}
+
+/// Something lnshot did or found, such as making a link, described for the log.
+///
+/// As text, events are written with the user and app ID in front of the message,
+/// like `[12345678; 220] Skipping excluded app`.
+#[must_use = "events aren't written until they're logged"]
+pub struct Event {
+    level: Level,
+
+    /// What lnshot was doing, such as `link` or `watch`, which stays the same whatever the message says
+    action: &'static str,
+
+    message: String,
+    user: Option<String>,
+    appid: Option<u64>,
+    paths: Vec<PathBuf>,
+}
+
+impl Event {
+    pub fn new(level: Level, action: &'static str, message: impl Into<String>) -> Event {
+        Event {
+            level,
+            action,
+            message: message.into(),
+            user: None,
+            appid: None,
+            paths: Vec::new(),
+        }
+    }
+
+    pub fn info(action: &'static str, message: impl Into<String>) -> Event {
+        Event::new(Level::Info, action, message)
+    }
+
+    pub fn warn(action: &'static str, message: impl Into<String>) -> Event {
+        Event::new(Level::Warn, action, message)
+    }
+
+    pub fn error(action: &'static str, message: impl Into<String>) -> Event {
+        Event::new(Level::Error, action, message)
+    }
+
+    pub fn debug(action: &'static str, message: impl Into<String>) -> Event {
+        Event::new(Level::Debug, action, message)
+    }
+
+    pub fn trace(action: &'static str, message: impl Into<String>) -> Event {
+        Event::new(Level::Trace, action, message)
+    }
+
+    /// Notes which user the event is about, by their account ID or name
+    pub fn user(mut self, user: impl ToString) -> Event {
+        self.user = Some(user.to_string());
+        self
+    }
+
+    /// Notes which app the event is about
+    pub fn appid(mut self, appid: u64) -> Event {
+        self.appid = Some(appid);
+        self
+    }
+
+    /// Notes a path the event is about, such as a link which was made
+    pub fn path(mut self, path: impl AsRef<Path>) -> Event {
+        self.paths.push(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Sends the event to `tracing`, to be written to the log file, or standard output if there isn't one,
+    /// in the chosen format, if its level is being logged.
+    /// Any user or app it doesn't name is taken from the spans it's logged inside.
+    pub fn log(self) {
+        if !self.level.is_enabled() {
+            return;
+        }
+
+        let paths = (!self.paths.is_empty()).then(|| {
+            serde_json::to_string(
+                &self
+                    .paths
+                    .iter()
+                    .map(|path| path.to_string_lossy())
+                    .collect::<Vec<_>>(),
+            )
+            .expect("paths are always serialisable, as they're written lossily")
+        });
+
+        macro_rules! emit {
+            ($level:expr) => {
+                tracing::event!(
+                    target: TARGET,
+                    $level,
+                    action = self.action,
+                    user = self.user.as_deref(),
+                    appid = self.appid,
+                    paths = paths.as_deref(),
+                    "{}",
+                    self.message
+                )
+            };
+        }
+
+        match self.level {
+            Level::Error => emit!(tracing::Level::ERROR),
+            Level::Warn => emit!(tracing::Level::WARN),
+            Level::Info => emit!(tracing::Level::INFO),
+            Level::Debug => emit!(tracing::Level::DEBUG),
+            Level::Trace => emit!(tracing::Level::TRACE),
+        }
+    }
+}
+
+/// An event with the user and app it's about worked out, ready to be written
+struct Line<'a> {
+    level: Level,
+    action: &'a str,
+    message: &'a str,
+    user: Option<&'a str>,
+    appid: Option<u64>,
+    paths: &'a [String],
+}
+
+/// An event as written to the log in JSON format, one object per line
+#[derive(Serialize)]
+struct JsonLine<'a> {
+    timestamp: String,
+    level: &'a str,
+    action: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    appid: Option<u64>,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    paths: &'a [String],
+    message: &'a str,
+}
+
+impl Line<'_> {
+    fn render(&self, format: LogFormat) -> String {
+        match format {
+            LogFormat::Text => match (self.user, self.appid) {
+                (Some(user), Some(appid)) => format!("[{}; {:20}] {}", user, appid, self.message),
+                (Some(user), None) => format!("[{}] {}", user, self.message),
+                (None, Some(appid)) => format!("[{:20}] {}", appid, self.message),
+                (None, None) => self.message.to_string(),
+            },
+            LogFormat::Json => serde_json::to_string(&JsonLine {
+                timestamp: timestamp(SystemTime::now()),
+                level: self.level.name(),
+                action: self.action,
+                user: self.user,
+                appid: self.appid,
+                paths: self.paths,
+                message: self.message,
+            })
+            .expect("log lines are always serialisable"),
+        }
+    }
+}
+
+/// Writes a rendered line to the log file, or standard output if there isn't one
+fn write_line(time: SystemTime, line: String) {
+    match LOG_FILE
+        .lock()
+        .unwrap_or_else(|error| error.into_inner())
+        .as_mut()
+    {
+        Some(log_file) => {
+            // Unlike a terminal or journald, nothing else records when each line was written
+            let line = match format() {
+                LogFormat::Text => format!("{} {}", timestamp(time), line),
+                LogFormat::Json => line,
+            };
+
+            if let Err(error) = log_file.write_line(&line) {
+                eprintln!("Failed to write to log file {:?}: {}", log_file.path, error);
+                eprintln!("{}", line);
+            }
+        }
+        None if TO_STDERR.load(Ordering::Relaxed) => eprintln!("{}", line),
+        None => println!("{}", line),
+    }
+}
+
+/// Formats a time as an RFC 3339 timestamp in UTC, such as `2024-03-09T14:05:00.123Z`
+pub fn timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since_epoch.as_secs();
+    let (year, month, day) = civil_date(seconds / 86400);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        seconds % 86400 / 3600,
+        seconds % 3600 / 60,
+        seconds % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+/// Works out the year, month and day some number of days after the Unix epoch,
+/// using Howard Hinnant's `civil_from_days`
+fn civil_date(days: u64) -> (u64, u64, u64) {
+    let days = days + 719468;
+    let era = days / 146097;
+    let day_of_era = days % 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Makes an empty temporary folder of this test's own to log into
+    fn log_dir(name: &str) -> PathBuf {
+        let log_dir =
+            std::env::temp_dir().join(format!("lnshot-log-test-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&log_dir);
+        std::fs::create_dir_all(&log_dir).unwrap();
+        log_dir
+    }
+
+    #[test]
+    fn reads_back_the_rotation_it_writes() {
+        for rotation in ["10M", "512K", "1000", "daily", "never"] {
+            assert_eq!(
+                rotation.parse::<LogRotation>().unwrap().to_string(),
+                rotation
+            );
+        }
+
+        assert_eq!(
+            "10M".parse::<LogRotation>().unwrap(),
+            LogRotation::Size(10 * 1024 * 1024)
+        );
+        assert_eq!(
+            "512k".parse::<LogRotation>().unwrap(),
+            LogRotation::Size(512 * 1024)
+        );
+        assert_eq!("Daily".parse::<LogRotation>().unwrap(), LogRotation::Daily);
+    }
+
+    #[test]
+    fn rejects_rotation_without_a_size() {
+        for rotation in ["0", "0M", "", "M", "-1K", "weekly"] {
+            assert!(
+                rotation.parse::<LogRotation>().is_err(),
+                "{:?} should be rejected",
+                rotation
+            );
+        }
+    }
+
+    #[test]
+    fn works_out_dates_across_leap_days_and_years() {
+        assert_eq!(civil_date(0), (1970, 1, 1));
+        assert_eq!(civil_date(19722), (2023, 12, 31));
+        assert_eq!(civil_date(19723), (2024, 1, 1));
+        assert_eq!(civil_date(19782), (2024, 2, 29));
+        assert_eq!(civil_date(19783), (2024, 3, 1));
+        assert_eq!(civil_date(11016), (2000, 2, 29));
+        assert_eq!(civil_date(47540), (2100, 2, 28));
+        assert_eq!(civil_date(47541), (2100, 3, 1));
+    }
+
+    #[test]
+    fn rotates_by_size_keeping_the_newest_logs() {
+        let log_dir = log_dir("size");
+        let log_path = log_dir.join("lnshot.log");
+
+        // Each line fills the file, so every line after the first starts a new one
+        let mut log_file = LogFile::open(log_path.clone(), LogRotation::Size(8)).unwrap();
+        for number in 0..=KEPT_LOGS + 1 {
+            log_file.write_line(&format!("line {}", number)).unwrap();
+        }
+
+        let read = |path: &Path| std::fs::read_to_string(path).unwrap();
+        assert_eq!(read(&log_path), format!("line {}\n", KEPT_LOGS + 1));
+        for number in 1..=KEPT_LOGS {
+            assert_eq!(
+                read(&log_file.rotated_path(number)),
+                format!("line {}\n", KEPT_LOGS + 1 - number)
+            );
+        }
+        assert!(!log_file.rotated_path(KEPT_LOGS + 1).exists());
+
+        std::fs::remove_dir_all(&log_dir).unwrap();
+    }
+
+    #[test]
+    fn rotates_daily_naming_logs_after_their_day() {
+        let log_dir = log_dir("daily");
+        let log_path = log_dir.join("lnshot.log");
+
+        let mut log_file = LogFile::open(log_path.clone(), LogRotation::Daily).unwrap();
+        log_file.write_line("today").unwrap();
+        assert!(!log_file.rotated_path("2024-02-29").exists());
+
+        // A file started on a leap day is set aside under that date once anything is written on another day
+        log_file.day = 19782;
+        log_file.write_line("tomorrow").unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(log_file.rotated_path("2024-02-29")).unwrap(),
+            "today\n"
+        );
+        assert_eq!(std::fs::read_to_string(&log_path).unwrap(), "tomorrow\n");
+
+        std::fs::remove_dir_all(&log_dir).unwrap();
+    }
+}
@@ -0,0 +1,440 @@
+//! A record kept inside the managed directory of what lnshot has made there
+
+use crate::log::Event;
+use crate::operations::Operations;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Name of the manifest file inside the managed directory
+pub const FILE_NAME: &str = ".lnshot.json";
+
+/// A link lnshot has made inside the managed directory
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LinkRecord {
+    /// The app's screenshot folder inside Steam's `userdata` directory
+    pub source: PathBuf,
+
+    pub appid: u64,
+
+    /// SteamID64 of the user the link was made for
+    pub user: String,
+
+    /// When the link was made, in seconds since the Unix epoch
+    pub created: u64,
+}
+
+/// What lnshot has made inside the managed directory, so it can be found again later
+#[derive(Debug)]
+pub struct Manifest {
+    path: PathBuf,
+
+    /// Names of users' folders, by their SteamID64
+    users: BTreeMap<String, String>,
+
+    /// Links lnshot has made, by their path inside the managed directory
+    links: BTreeMap<String, LinkRecord>,
+
+    /// Empty folders lnshot made for apps without screenshots yet, by their path inside the managed directory,
+    /// with the SteamID64 of the user they were made for
+    placeholders: BTreeMap<String, String>,
+
+    /// Whether the manifest was written by a version of lnshot which records links
+    tracks_links: bool,
+
+    changed: bool,
+}
+
+/// The manifest as it's written to the managed directory
+#[derive(Serialize)]
+struct ManifestFile<'a> {
+    users: &'a BTreeMap<String, String>,
+    links: &'a BTreeMap<String, LinkRecord>,
+    placeholders: &'a BTreeMap<String, String>,
+}
+
+impl Manifest {
+    /// Reads the manifest from the managed directory.
+    /// A missing or unreadable manifest is treated as empty, as it can be rebuilt.
+    /// Links which have since been removed are forgotten.
+    pub fn load(screenshots_dir: &Path) -> Manifest {
+        let path = screenshots_dir.join(FILE_NAME);
+        let mut users = BTreeMap::new();
+        let mut links = BTreeMap::new();
+        let mut placeholders = BTreeMap::new();
+        let mut tracks_links = false;
+        let mut changed = false;
+
+        if let Ok(contents) = std::fs::read_to_string(&path) {
+            match serde_json::from_str::<Value>(&contents) {
+                Ok(manifest) => {
+                    let user_folders = manifest
+                        .get("users")
+                        .and_then(Value::as_object)
+                        .into_iter()
+                        .flatten();
+
+                    for (steamid_str, folder_name) in user_folders {
+                        if let Some(folder_name) = folder_name.as_str() {
+                            users.insert(steamid_str.clone(), folder_name.to_string());
+                        }
+                    }
+
+                    if let Some(link_records) = manifest.get("links").and_then(Value::as_object) {
+                        tracks_links = true;
+
+                        for (link_path, link_record) in link_records {
+                            let Some(link_record) = read_link_record(link_record) else {
+                                continue;
+                            };
+
+                            if screenshots_dir.join(link_path).symlink_metadata().is_err() {
+                                changed = true;
+                                continue;
+                            }
+
+                            links.insert(link_path.clone(), link_record);
+                        }
+                    }
+
+                    let placeholder_records = manifest
+                        .get("placeholders")
+                        .and_then(Value::as_object)
+                        .into_iter()
+                        .flatten();
+
+                    for (placeholder_path, steamid_str) in placeholder_records {
+                        let Some(steamid_str) = steamid_str.as_str() else {
+                            continue;
+                        };
+
+                        // Placeholders which have been removed, or replaced with links, are forgotten
+                        if !screenshots_dir
+                            .join(placeholder_path)
+                            .symlink_metadata()
+                            .is_ok_and(|metadata| metadata.is_dir())
+                        {
+                            changed = true;
+                            continue;
+                        }
+
+                        placeholders.insert(placeholder_path.clone(), steamid_str.to_string());
+                    }
+                }
+                Err(error) => Event::warn(
+                    "manifest",
+                    format!("Ignoring unreadable manifest {:?}: {:#}", path, error),
+                )
+                .path(&path)
+                .log(),
+            }
+        }
+
+        Manifest {
+            path,
+            users,
+            links,
+            placeholders,
+            tracks_links,
+            changed,
+        }
+    }
+
+    /// Whether the manifest records which links lnshot made.
+    /// Manifests from older versions of lnshot don't, so their links can only be recognised by where they point.
+    pub fn tracks_links(&self) -> bool {
+        self.tracks_links
+    }
+
+    /// Checks whether lnshot made the link at a path inside the managed directory.
+    /// Without a record of links, every link is assumed to be lnshot's, as older versions did.
+    pub fn owns_link(&self, link_path: &Path) -> bool {
+        !self.tracks_links || self.link(link_path).is_some()
+    }
+
+    /// The record of the link at a path inside the managed directory, if lnshot made it
+    pub fn link(&self, link_path: &Path) -> Option<&LinkRecord> {
+        self.links.get(&self.link_key(link_path)?)
+    }
+
+    /// Finds the paths of every link lnshot made to the given screenshot folder
+    pub fn links_to(&self, source: &Path) -> Vec<PathBuf> {
+        let Some(screenshots_dir) = self.path.parent() else {
+            return Vec::new();
+        };
+
+        self.links
+            .iter()
+            .filter(|(_link_key, link_record)| link_record.source == source)
+            .map(|(link_key, _link_record)| screenshots_dir.join(link_key))
+            .collect()
+    }
+
+    /// Names of the links lnshot made directly inside a user's folder for the user's apps other than `appid`
+    pub fn other_link_names(&self, user_dir: &Path, steamid_str: &str, appid: u64) -> Vec<String> {
+        let Some(user_dir_key) = self.link_key(user_dir) else {
+            return Vec::new();
+        };
+
+        self.links
+            .iter()
+            .filter(|(_link_key, link_record)| {
+                link_record.user == steamid_str && link_record.appid != appid
+            })
+            .filter_map(|(link_key, _link_record)| {
+                let link_key = Path::new(link_key);
+
+                if link_key.parent()? != Path::new(&user_dir_key) {
+                    return None;
+                }
+
+                Some(link_key.file_name()?.to_string_lossy().into_owned())
+            })
+            .collect()
+    }
+
+    /// Records a link lnshot has made. Links which are already recorded keep their original timestamps.
+    pub fn record_link(&mut self, link_path: &Path, source: &Path, appid: u64, steamid_str: &str) {
+        let Some(link_key) = self.link_key(link_path) else {
+            return;
+        };
+
+        if let Some(link_record) = self.links.get(&link_key) {
+            if link_record.source == source
+                && link_record.appid == appid
+                && link_record.user == steamid_str
+            {
+                return;
+            }
+        }
+
+        let created = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0);
+
+        // A link made in place of a placeholder replaces it
+        self.placeholders.remove(&link_key);
+        self.links.insert(
+            link_key,
+            LinkRecord {
+                source: source.to_path_buf(),
+                appid,
+                user: steamid_str.to_string(),
+                created,
+            },
+        );
+        self.changed = true;
+    }
+
+    /// Forgets a link which has been removed
+    pub fn forget_link(&mut self, link_path: &Path) {
+        if let Some(link_key) = self.link_key(link_path) {
+            if self.links.remove(&link_key).is_some() {
+                self.changed = true;
+            }
+        }
+    }
+
+    /// Records an empty placeholder folder lnshot has made for a user
+    pub fn record_placeholder(&mut self, placeholder_path: &Path, steamid_str: &str) {
+        let Some(placeholder_key) = self.link_key(placeholder_path) else {
+            return;
+        };
+
+        if self.placeholders.get(&placeholder_key).map(String::as_str) != Some(steamid_str) {
+            self.placeholders
+                .insert(placeholder_key, steamid_str.to_string());
+            self.changed = true;
+        }
+    }
+
+    /// Checks whether lnshot made the placeholder folder at a path inside the managed directory
+    pub fn is_placeholder(&self, path: &Path) -> bool {
+        self.link_key(path)
+            .is_some_and(|placeholder_key| self.placeholders.contains_key(&placeholder_key))
+    }
+
+    /// Forgets a placeholder folder which has been removed
+    pub fn forget_placeholder(&mut self, placeholder_path: &Path) {
+        if let Some(placeholder_key) = self.link_key(placeholder_path) {
+            if self.placeholders.remove(&placeholder_key).is_some() {
+                self.changed = true;
+            }
+        }
+    }
+
+    /// Updates the records of links inside a folder which has been moved
+    pub fn move_links(&mut self, previous_dir: &Path, dir: &Path) {
+        let (Some(previous_dir), Some(dir)) = (self.link_key(previous_dir), self.link_key(dir))
+        else {
+            return;
+        };
+
+        let moved_keys = self
+            .links
+            .keys()
+            .filter_map(|link_key| {
+                let relative_path = Path::new(link_key).strip_prefix(&previous_dir).ok()?;
+                let moved_key = Path::new(&dir).join(relative_path);
+
+                Some((link_key.clone(), moved_key.to_string_lossy().into_owned()))
+            })
+            .collect::<Vec<_>>();
+
+        for (link_key, moved_key) in moved_keys {
+            if let Some(link_record) = self.links.remove(&link_key) {
+                self.links.insert(moved_key, link_record);
+                self.changed = true;
+            }
+        }
+    }
+
+    /// Works out the key for a path inside the managed directory, relative to the directory
+    fn link_key(&self, link_path: &Path) -> Option<String> {
+        let screenshots_dir = self.path.parent()?;
+
+        link_path
+            .strip_prefix(screenshots_dir)
+            .ok()
+            .map(|relative_path| relative_path.to_string_lossy().into_owned())
+    }
+
+    /// Whether the manifest records any users' folders
+    pub fn has_user_folders(&self) -> bool {
+        !self.users.is_empty()
+    }
+
+    /// Checks whether a folder directly inside the managed directory is one lnshot made for a user
+    pub fn is_user_folder(&self, folder_name: &OsStr) -> bool {
+        self.users
+            .values()
+            .any(|user_folder| OsStr::new(user_folder) == folder_name)
+    }
+
+    /// The name of a user's folder when lnshot last made it
+    pub fn user_folder(&self, steamid_str: &str) -> Option<&str> {
+        self.users.get(steamid_str).map(String::as_str)
+    }
+
+    /// Records the name of a user's folder
+    pub fn set_user_folder(&mut self, steamid_str: &str, folder_name: &str) {
+        if self.user_folder(steamid_str) != Some(folder_name) {
+            self.users
+                .insert(steamid_str.to_string(), folder_name.to_string());
+            self.changed = true;
+        }
+    }
+
+    /// Takes out everything recorded for a user, so their folder can be processed on a thread of its own.
+    /// The user's records are put back with [`Manifest::merge`].
+    pub fn take_user(&mut self, steamid_str: &str) -> Manifest {
+        let mut users = BTreeMap::new();
+        if let Some(folder_name) = self.users.remove(steamid_str) {
+            users.insert(steamid_str.to_string(), folder_name);
+        }
+
+        let link_keys = self
+            .links
+            .iter()
+            .filter(|(_link_key, link_record)| link_record.user == steamid_str)
+            .map(|(link_key, _link_record)| link_key.clone())
+            .collect::<Vec<_>>();
+        let links = link_keys
+            .into_iter()
+            .filter_map(|link_key| {
+                let link_record = self.links.remove(&link_key)?;
+                Some((link_key, link_record))
+            })
+            .collect();
+
+        let placeholder_keys = self
+            .placeholders
+            .iter()
+            .filter(|(_placeholder_key, user)| user.as_str() == steamid_str)
+            .map(|(placeholder_key, _user)| placeholder_key.clone())
+            .collect::<Vec<_>>();
+        let placeholders = placeholder_keys
+            .into_iter()
+            .filter_map(|placeholder_key| {
+                let user = self.placeholders.remove(&placeholder_key)?;
+                Some((placeholder_key, user))
+            })
+            .collect();
+
+        Manifest {
+            path: self.path.clone(),
+            users,
+            links,
+            placeholders,
+            tracks_links: self.tracks_links,
+            changed: false,
+        }
+    }
+
+    /// Takes out what's recorded at a single path, so a link can be made there on a thread of its own.
+    /// The records are put back with [`Manifest::merge`].
+    pub fn take_link(&mut self, link_path: &Path) -> Manifest {
+        let mut links = BTreeMap::new();
+        let mut placeholders = BTreeMap::new();
+
+        if let Some(link_key) = self.link_key(link_path) {
+            if let Some(link_record) = self.links.remove(&link_key) {
+                links.insert(link_key.clone(), link_record);
+            }
+
+            if let Some(user) = self.placeholders.remove(&link_key) {
+                placeholders.insert(link_key, user);
+            }
+        }
+
+        Manifest {
+            path: self.path.clone(),
+            users: BTreeMap::new(),
+            links,
+            placeholders,
+            tracks_links: self.tracks_links,
+            changed: false,
+        }
+    }
+
+    /// Puts back records taken out with [`Manifest::take_user`] or [`Manifest::take_link`],
+    /// along with any changes made to them
+    pub fn merge(&mut self, other: Manifest) {
+        self.users.extend(other.users);
+        self.links.extend(other.links);
+        self.placeholders.extend(other.placeholders);
+        self.changed |= other.changed;
+    }
+
+    /// Writes the manifest back to the managed directory, if anything has changed
+    pub fn save(&mut self, operations: &mut Operations) -> Result<()> {
+        if !self.changed {
+            return Ok(());
+        }
+
+        let mut contents = serde_json::to_string_pretty(&ManifestFile {
+            users: &self.users,
+            links: &self.links,
+            placeholders: &self.placeholders,
+        })
+        .with_context(|| format!("Failed to write manifest {:?}", self.path))?;
+        contents.push('\n');
+
+        operations
+            .write_file(&self.path, &contents)
+            .with_context(|| format!("Failed to write manifest {:?}", self.path))?;
+
+        self.changed = false;
+        Ok(())
+    }
+}
+
+/// Reads a link's record from the manifest, skipping any which are incomplete
+fn read_link_record(value: &Value) -> Option<LinkRecord> {
+    LinkRecord::deserialize(value).ok()
+}
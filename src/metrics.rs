@@ -0,0 +1,209 @@
+//! Serving what the daemon has done at `/metrics` on a local port, in Prometheus' text format
+
+use crate::linker::LinkStats;
+use crate::log::Event;
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// What the daemon has done since it started
+#[derive(Debug, Default)]
+pub struct Metrics {
+    /// Changes to screenshot folders acted on
+    pub events_processed: u64,
+
+    /// What the daemon's linker has done, including when linking everything again
+    pub link_stats: LinkStats,
+
+    /// Failures outside of linking, such as the file system watcher reporting an error
+    pub errors: u64,
+
+    /// New screenshots, by the display name of the user who took them
+    pub screenshots: BTreeMap<String, u64>,
+
+    pub paused: bool,
+
+    /// When the daemon last acted on a change
+    pub last_event: Option<SystemTime>,
+}
+
+impl Metrics {
+    /// Formats the metrics in Prometheus' text format
+    fn render(&self, started: SystemTime) -> String {
+        let mut output = String::new();
+
+        let mut metric = |name: &str, kind: &str, help: &str, samples: &[(String, f64)]| {
+            let _ = writeln!(output, "# HELP lnshot_{} {}", name, help);
+            let _ = writeln!(output, "# TYPE lnshot_{} {}", name, kind);
+
+            for (labels, value) in samples {
+                let _ = writeln!(output, "lnshot_{}{} {}", name, labels, value);
+            }
+        };
+
+        let counter = |value: u64| vec![(String::new(), value as f64)];
+
+        metric(
+            "events_processed_total",
+            "counter",
+            "Changes to screenshot folders the daemon has acted on.",
+            &counter(self.events_processed),
+        );
+        metric(
+            "symlinks_created_total",
+            "counter",
+            "Links, aliases and copied folders made.",
+            &counter(self.link_stats.created),
+        );
+        metric(
+            "symlinks_removed_total",
+            "counter",
+            "Links and aliases removed.",
+            &counter(self.link_stats.removed),
+        );
+        metric(
+            "errors_total",
+            "counter",
+            "Failures, such as links which couldn't be made or errors from the file system watcher.",
+            &counter(self.link_stats.errors + self.errors),
+        );
+        metric(
+            "screenshots_total",
+            "counter",
+            "New screenshots, by user.",
+            &self
+                .screenshots
+                .iter()
+                .map(|(user, count)| (format!("{{user=\"{}\"}}", label_value(user)), *count as f64))
+                .collect::<Vec<_>>(),
+        );
+        metric(
+            "paused",
+            "gauge",
+            "Whether the daemon is paused, and not acting on changes.",
+            &counter(self.paused as u64),
+        );
+        metric(
+            "start_time_seconds",
+            "gauge",
+            "When the daemon started, in seconds since the Unix epoch.",
+            &[(String::new(), unix_seconds(started))],
+        );
+
+        if let Some(last_event) = self.last_event {
+            metric(
+                "last_event_timestamp_seconds",
+                "gauge",
+                "When the daemon last acted on a change, in seconds since the Unix epoch.",
+                &[(String::new(), unix_seconds(last_event))],
+            );
+        }
+
+        output
+    }
+}
+
+/// Held by the running daemon, for updating the metrics it serves
+pub struct MetricsServer {
+    metrics: Arc<Mutex<Metrics>>,
+}
+
+impl MetricsServer {
+    /// Starts serving metrics on the port, only to this computer
+    pub fn start(port: u16) -> Result<MetricsServer> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))
+            .with_context(|| format!("Failed to serve metrics on port {}", port))?;
+
+        let metrics = Arc::new(Mutex::new(Metrics::default()));
+        let thread_metrics = metrics.clone();
+        let started = SystemTime::now();
+
+        std::thread::Builder::new()
+            .name("metrics".to_string())
+            .spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    let _ = stream.set_read_timeout(Some(Duration::from_secs(5)));
+
+                    if let Err(error) = answer(stream, &thread_metrics, started) {
+                        Event::error(
+                            "metrics",
+                            format!("Failed to answer a metrics request: {}", error),
+                        )
+                        .log();
+                    }
+                }
+            })
+            .with_context(|| "Failed to start the metrics thread")?;
+
+        Event::info(
+            "metrics",
+            format!("Serving metrics at http://localhost:{}/metrics", port),
+        )
+        .log();
+
+        Ok(MetricsServer { metrics })
+    }
+
+    /// Changes the metrics, which are served as they are once this returns
+    pub fn update(&self, update: impl FnOnce(&mut Metrics)) {
+        update(
+            &mut self
+                .metrics
+                .lock()
+                .unwrap_or_else(|error| error.into_inner()),
+        );
+    }
+}
+
+/// Reads an HTTP request, and answers it with the metrics if it's for them
+fn answer(stream: TcpStream, metrics: &Mutex<Metrics>, started: SystemTime) -> std::io::Result<()> {
+    let mut reader = BufReader::new(&stream);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    // The rest of the request doesn't matter, but is read so the client sees the whole response
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let mut words = request_line.split_whitespace();
+    let (status, body) = match (words.next(), words.next()) {
+        (Some("GET"), Some("/metrics")) => (
+            "200 OK",
+            metrics
+                .lock()
+                .unwrap_or_else(|error| error.into_inner())
+                .render(started),
+        ),
+        (Some("GET"), _) => ("404 Not Found", "Metrics are at /metrics\n".to_string()),
+        _ => ("405 Method Not Allowed", String::new()),
+    };
+
+    write!(
+        &stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
+
+/// Escapes a label's value, as Prometheus expects inside double quotes
+fn label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn unix_seconds(time: SystemTime) -> f64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs_f64())
+        .unwrap_or(0.0)
+}
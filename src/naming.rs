@@ -0,0 +1,455 @@
+use crate::folder_name::{finalize_affixed_folder_name, finalize_folder_name_replacing, is_blank};
+use crate::locator::SteamUser;
+use crate::log::Event;
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsStr;
+use std::fmt;
+use std::str::FromStr;
+use steamlocate::{Shortcut, SteamApp};
+
+lazy_static! {
+    static ref BUILT_IN_APPS: HashMap<u64, &'static str> = HashMap::from([
+        (0, "Unknown"),
+        (5, "Dedicated Server"),
+        (7, "Steam Client"),
+        (910, "Steam Media Player"),
+    ]);
+}
+
+/// Source of names for apps' screenshot folders
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NameSource {
+    /// Names chosen for specific app IDs in the config file's `[names]` table
+    Map,
+
+    /// Names of apps built into Steam itself, like the Steam Client
+    Builtin,
+
+    /// Names of installed Steam games, as shown in the Steam client.
+    /// Falls back to the install directory's name if the app manifest doesn't have one.
+    /// With `--deck`, games on microSD cards Steam isn't currently using are included too.
+    Installed,
+
+    /// Names of non-Steam game shortcuts
+    Shortcut,
+
+    /// Names from the full list of Steam apps, for games which aren't installed.
+    /// The list is only available once downloaded with `lnshot update-app-list`.
+    AppList,
+
+    /// Names looked up from the Steam store, for games which aren't installed.
+    /// Results are cached, and `--offline` turns this source off.
+    Web,
+
+    /// The app ID itself
+    Numeric,
+}
+
+/// How users' folders are named
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UserFolderStyle {
+    /// The user's display name. Folders are renamed when it changes.
+    Persona,
+
+    /// The user's account ID, as used for their folder in Steam's `userdata` directory
+    Accountid,
+
+    /// The user's SteamID64
+    Steamid64,
+}
+
+/// Checks whether a screenshot folder's app ID belongs to a non-Steam shortcut.
+///
+/// Screenshot folders for shortcuts may be named after either the shortcut's app ID
+/// with its top bits masked off, or its full 64-bit Steam ID.
+pub fn shortcut_matches_appid(shortcut: &Shortcut, appid: u64) -> bool {
+    u64::from(shortcut.appid & 0x7fffff) == appid || shortcut.steam_id() == appid
+}
+
+/// Looks up human-readable names for app IDs, from each of the available sources
+pub struct AppNameResolver<'a> {
+    /// Names chosen by the user, checked where `map` comes in the order
+    pub custom_names: &'a HashMap<u64, String>,
+    pub steam_apps: &'a HashMap<u32, Option<SteamApp>>,
+    pub steam_shortcuts: &'a [Shortcut],
+    pub name_resolution_order: &'a [NameSource],
+    pub dlc_parents: &'a HashMap<u32, u32>,
+    pub looked_up_names: &'a LookedUpNames,
+}
+
+/// Names for apps which aren't installed, found ahead of time as they can't be found on demand
+#[derive(Default, Debug)]
+pub struct LookedUpNames {
+    /// Names found in the downloaded list of Steam apps
+    pub app_list: HashMap<u64, String>,
+
+    /// Names looked up from the Steam store
+    pub web: HashMap<u64, String>,
+
+    /// Names of games installed on removable drives Steam isn't currently using,
+    /// such as a Steam Deck's other microSD cards
+    pub removable_libraries: HashMap<u64, String>,
+}
+
+impl<'a> AppNameResolver<'a> {
+    /// Works out the human-readable name for an app ID, checking each source in the configured order.
+    /// DLC is named after its base game where possible, unless the user chose a name for the DLC itself.
+    ///
+    /// Returns `None` if the app ID can't be resolved to a name, or the `numeric` source is reached.
+    pub fn resolve(&self, appid: u64) -> Result<Option<&'a OsStr>> {
+        if let Some(parent_appid) = self.dlc_parents.get(&(appid as u32)) {
+            if self.name_resolution_order.contains(&NameSource::Map) {
+                if let Some(custom_name) = self.custom_names.get(&appid) {
+                    return Ok(Some(OsStr::new(custom_name)));
+                }
+            }
+
+            if let Some(app_name) = self.resolve_from_sources(u64::from(*parent_appid))? {
+                return Ok(Some(app_name));
+            }
+        }
+
+        self.resolve_from_sources(appid)
+    }
+
+    /// Works out the human-readable name for an app ID, checking each source in the configured order
+    fn resolve_from_sources(&self, appid: u64) -> Result<Option<&'a OsStr>> {
+        for source in self.name_resolution_order {
+            let app_name = match source {
+                NameSource::Map => self.custom_names.get(&appid).map(OsStr::new),
+                NameSource::Builtin => BUILT_IN_APPS.get(&appid).map(OsStr::new),
+                NameSource::Installed => match self.steam_apps.get(&(appid as u32)) {
+                    Some(Some(app)) => Some(match &app.name {
+                        Some(name) if !name.trim().is_empty() => OsStr::new(name),
+                        _ => app
+                            .path
+                            .file_name()
+                            .with_context(|| "Failed to retrieve file name from install path")?,
+                    }),
+                    _ => self
+                        .looked_up_names
+                        .removable_libraries
+                        .get(&appid)
+                        .map(OsStr::new),
+                },
+                NameSource::Shortcut => self
+                    .steam_shortcuts
+                    .iter()
+                    .find(|shortcut| shortcut_matches_appid(shortcut, appid))
+                    .map(|shortcut| OsStr::new(&shortcut.app_name)),
+                NameSource::AppList => self.looked_up_names.app_list.get(&appid).map(OsStr::new),
+                NameSource::Web => self.looked_up_names.web.get(&appid).map(OsStr::new),
+                NameSource::Numeric => return Ok(None),
+            };
+
+            if app_name.is_some() {
+                return Ok(app_name);
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+/// A pattern for apps' folder names, such as `{name} [{appid}]`.
+///
+/// `{name}` is replaced with the app's name, `{appid}` with its app ID,
+/// and `{user}` with the display name of the user whose screenshots they are.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NameTemplate {
+    template: String,
+}
+
+impl FromStr for NameTemplate {
+    type Err = anyhow::Error;
+
+    fn from_str(template: &str) -> Result<Self> {
+        let mut rest = template;
+        let mut has_app_placeholder = false;
+
+        while let Some(start) = rest.find('{') {
+            let end = rest[start..]
+                .find('}')
+                .with_context(|| format!("Name template {:?} has an unclosed {{", template))?;
+
+            match &rest[start + 1..start + end] {
+                "name" | "appid" => has_app_placeholder = true,
+                "user" => {}
+                placeholder => anyhow::bail!(
+                    "Name template {:?} has an unknown placeholder {{{}}}; use {{name}}, {{appid}} or {{user}}",
+                    template,
+                    placeholder
+                ),
+            }
+
+            rest = &rest[start + end + 1..];
+        }
+
+        // Without the app's name or ID, every folder would be named the same
+        if !has_app_placeholder {
+            anyhow::bail!(
+                "Name template {:?} must include {{name}} or {{appid}}",
+                template
+            );
+        }
+
+        Ok(NameTemplate {
+            template: template.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for NameTemplate {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str(&self.template)
+    }
+}
+
+impl NameTemplate {
+    /// Fills in the template's placeholders for an app
+    pub fn render(&self, appid: u64, app_name: &str, user_name: &str) -> String {
+        fill(&self.template, appid, app_name, user_name)
+    }
+
+    /// Fills in the parts of the template before and after `{name}` for an app, so the app's name can be
+    /// shortened to fit between them. Templates without exactly one `{name}` don't have a prefix and suffix.
+    pub fn render_affixes(&self, appid: u64, user_name: &str) -> Option<(String, String)> {
+        // Templates can't escape braces, so `{name}` is always the placeholder
+        let mut parts = self.template.split("{name}");
+        let (before, after) = (parts.next()?, parts.next()?);
+
+        if parts.next().is_some() {
+            return None;
+        }
+
+        Some((
+            fill(before, appid, "", user_name),
+            fill(after, appid, "", user_name),
+        ))
+    }
+}
+
+/// Fills in the placeholders in part of a name template
+fn fill(template: &str, appid: u64, app_name: &str, user_name: &str) -> String {
+    let mut rendered = String::new();
+    let mut rest = template;
+
+    // Placeholders are filled in one pass, so names which look like placeholders are left alone
+    while let Some((before, after)) = rest.split_once('{') {
+        let (placeholder, after) = after
+            .split_once('}')
+            .expect("templates are checked for unclosed placeholders when parsed");
+
+        rendered.push_str(before);
+
+        match placeholder {
+            "name" => rendered.push_str(app_name),
+            "appid" => rendered.push_str(&appid.to_string()),
+            _ => rendered.push_str(user_name),
+        }
+
+        rest = after;
+    }
+
+    rendered.push_str(rest);
+    rendered
+}
+
+/// Works out the folder name for a user from their display name, falling back
+/// to their account ID if the display name has nothing visible in it.
+/// Characters which can't appear in folder names are replaced with `replacement_character`.
+pub fn user_folder_name(
+    steamid_str: &str,
+    persona_name: &str,
+    account_id: u64,
+    replacement_character: char,
+) -> String {
+    if is_blank(persona_name) {
+        Event::warn(
+            "user_folder",
+            format!(
+                "Display name {:?} is blank; naming folder after account ID {} instead",
+                persona_name, account_id
+            ),
+        )
+        .user(steamid_str)
+        .log();
+
+        return account_id.to_string();
+    }
+
+    finalize_folder_name_replacing(persona_name, &HashSet::new(), replacement_character)
+}
+
+/// Suffix added to the folder names of users who share a display name, unless another is configured
+pub const DEFAULT_DUPLICATE_USER_SUFFIX: &str = " ({accountid})";
+
+/// Checks a duplicate user suffix contains something which differs between users
+pub fn is_valid_duplicate_user_suffix(suffix: &str) -> bool {
+    suffix.contains("{accountid}") || suffix.contains("{steamid64}")
+}
+
+/// Works out the folder names for users, by their SteamID64.
+///
+/// Folders named after IDs are always distinct. Users whose display names would give them the same folder, ignoring case, have `duplicate_suffix`
+/// added to their folder names to tell them apart, with `{accountid}` and `{steamid64}` replaced by their IDs.
+pub fn user_folder_names(
+    users: &[SteamUser],
+    style: UserFolderStyle,
+    replacement_character: char,
+    duplicate_suffix: &str,
+) -> HashMap<String, String> {
+    match style {
+        UserFolderStyle::Persona => {}
+        UserFolderStyle::Accountid => {
+            return users
+                .iter()
+                .map(|user| (user.steamid_str.clone(), user.account_id().to_string()))
+                .collect();
+        }
+        UserFolderStyle::Steamid64 => {
+            return users
+                .iter()
+                .map(|user| (user.steamid_str.clone(), user.steamid_str.clone()))
+                .collect();
+        }
+    }
+
+    let folder_names = users
+        .iter()
+        .map(|user| {
+            (
+                user,
+                user_folder_name(
+                    &user.steamid_str,
+                    &user.persona_name,
+                    user.account_id(),
+                    replacement_character,
+                ),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let mut name_counts = HashMap::new();
+    for (_user, folder_name) in &folder_names {
+        *name_counts.entry(folder_name.to_lowercase()).or_insert(0) += 1;
+    }
+
+    folder_names
+        .into_iter()
+        .map(|(user, folder_name)| {
+            if name_counts[&folder_name.to_lowercase()] < 2 {
+                return (user.steamid_str.clone(), folder_name);
+            }
+
+            let suffix = duplicate_suffix
+                .replace("{accountid}", &user.account_id().to_string())
+                .replace("{steamid64}", &user.steamid_str);
+
+            Event::info(
+                "user_folder",
+                format!(
+                    "Another user's folder would also be named {:?}; adding {:?} to tell them apart",
+                    folder_name, suffix
+                ),
+            )
+            .user(&user.steamid_str)
+            .log();
+
+            // The suffix is what tells the users apart, so only the display name is shortened to fit it
+            (
+                user.steamid_str.clone(),
+                finalize_affixed_folder_name(
+                    "",
+                    &folder_name,
+                    &suffix,
+                    &HashSet::new(),
+                    replacement_character,
+                ),
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_affixes_around_names() {
+        let name_template = "{appid} - {name} ({user})".parse::<NameTemplate>().unwrap();
+
+        assert_eq!(
+            name_template.render_affixes(620, "Tester"),
+            Some(("620 - ".to_string(), " (Tester)".to_string()))
+        );
+        assert_eq!(
+            name_template.render(620, "Portal 2", "Tester"),
+            "620 - Portal 2 (Tester)"
+        );
+    }
+
+    #[test]
+    fn renders_no_affixes_without_exactly_one_name() {
+        let name_template = "{appid}".parse::<NameTemplate>().unwrap();
+        assert_eq!(name_template.render_affixes(620, "Tester"), None);
+
+        let name_template = "{name}/{name}".parse::<NameTemplate>().unwrap();
+        assert_eq!(name_template.render_affixes(620, "Tester"), None);
+    }
+
+    #[test]
+    fn names_users_with_blank_display_names_after_their_account_id() {
+        assert_eq!(user_folder_name("76561197960266962", "", 1234, '_'), "1234");
+        assert_eq!(
+            user_folder_name("76561197960266962", "   ", 1234, '_'),
+            "1234"
+        );
+        assert_eq!(
+            user_folder_name("76561197960266962", "\t\u{200b}", 1234, '_'),
+            "1234"
+        );
+    }
+
+    #[test]
+    fn names_users_after_their_display_name() {
+        assert_eq!(
+            user_folder_name("76561197960266962", "Tester", 1234, '_'),
+            "Tester"
+        );
+        assert_eq!(
+            user_folder_name("76561197960266962", " A/B ", 1234, '_'),
+            "A_B"
+        );
+    }
+
+    #[test]
+    fn names_apps_from_the_map_where_it_comes_in_the_order() {
+        let custom_names = HashMap::from([(7, "My Steam".to_string())]);
+        let resolve = |name_resolution_order: &[NameSource]| {
+            AppNameResolver {
+                custom_names: &custom_names,
+                steam_apps: &HashMap::new(),
+                steam_shortcuts: &[],
+                name_resolution_order,
+                dlc_parents: &HashMap::new(),
+                looked_up_names: &LookedUpNames::default(),
+            }
+            .resolve(7)
+            .unwrap()
+            .map(OsStr::to_os_string)
+        };
+
+        assert_eq!(
+            resolve(&[NameSource::Map, NameSource::Builtin]),
+            Some("My Steam".into())
+        );
+        assert_eq!(
+            resolve(&[NameSource::Builtin, NameSource::Map]),
+            Some("Steam Client".into())
+        );
+        assert_eq!(resolve(&[NameSource::Numeric, NameSource::Map]), None);
+    }
+}
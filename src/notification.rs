@@ -0,0 +1,82 @@
+use crate::log::Event;
+use anyhow::{Context, Result};
+use notify_rust::Notification;
+use std::time::{Duration, Instant};
+
+/// Shortest time between summary notifications, so bursts of activity don't spam the user
+const SUMMARY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Shows a desktop notification with the platform's own notification system
+pub fn send(title: &str, body: &str) -> Result<()> {
+    Notification::new()
+        .appname("lnshot")
+        .summary(title)
+        .body(body)
+        .show()
+        .with_context(|| "Failed to show a desktop notification")?;
+
+    Ok(())
+}
+
+/// Collects newly-linked games, and sends a single notification summarising them
+/// once the batch is complete, at most once every [`SUMMARY_INTERVAL`]
+#[derive(Default)]
+pub struct SummaryNotifier {
+    linked_count: usize,
+    last_sent: Option<Instant>,
+}
+
+impl SummaryNotifier {
+    /// Records that a new game was linked
+    pub fn record_linked(&mut self) {
+        self.linked_count += 1;
+    }
+
+    /// How long until a pending summary can be sent, if there is one
+    pub fn time_until_due(&self) -> Option<Duration> {
+        if self.linked_count == 0 {
+            return None;
+        }
+
+        Some(
+            self.last_sent
+                .map(|last_sent| SUMMARY_INTERVAL.saturating_sub(last_sent.elapsed()))
+                .unwrap_or_default(),
+        )
+    }
+
+    /// Sends the pending summary, if there is one and enough time has passed since the last
+    pub fn flush(&mut self) {
+        if self.time_until_due() != Some(Duration::ZERO) {
+            return;
+        }
+
+        self.send_summary();
+    }
+
+    /// Sends the pending summary straight away, if there is one, such as when the daemon stops
+    pub fn finish(&mut self) {
+        if self.linked_count > 0 {
+            self.send_summary();
+        }
+    }
+
+    fn send_summary(&mut self) {
+        let body = if self.linked_count == 1 {
+            "Linked 1 new game".to_string()
+        } else {
+            format!("Linked {} new games", self.linked_count)
+        };
+
+        if let Err(error) = send("lnshot", &body) {
+            Event::error(
+                "notification",
+                format!("Error sending notification: {}", error),
+            )
+            .log();
+        }
+
+        self.linked_count = 0;
+        self.last_sent = Some(Instant::now());
+    }
+}
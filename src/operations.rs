@@ -0,0 +1,920 @@
+use crate::log::Event;
+use crate::plan::Step;
+use anyhow::{Context, Result};
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Component, Path, PathBuf, Prefix};
+use std::process::Command;
+use std::time::Duration;
+
+/// How many more times a change is tried after a transient error, such as a file briefly locked
+/// by antivirus on Windows, or a network file system which stopped responding for a moment
+const RETRIES: u32 = 3;
+
+/// How long to wait before trying a change again, doubling with each try
+const RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Carries out changes to the file system, or describes them in a script instead,
+/// refusing to touch anything inside read-only directories
+pub struct Operations {
+    mode: Mode,
+    read_only_dirs: Vec<PathBuf>,
+}
+
+/// How changes to the file system are carried out
+enum Mode {
+    /// Make changes to the file system directly
+    Execute,
+
+    /// Write the commands which would make the changes to a script,
+    /// leaving the file system untouched
+    EmitScript(Script),
+
+    /// Describe each change without making it
+    DryRun,
+
+    /// Record each change as a step of a plan, to be made later
+    Plan(Vec<Step>),
+}
+
+/// Where a script's commands are written
+enum Script {
+    File(File),
+
+    /// Commands from operations forked onto another thread, written to the file when they're joined
+    Buffer(Vec<u8>),
+}
+
+impl Write for Script {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Script::File(file) => file.write(buf),
+            Script::Buffer(buffer) => buffer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Script::File(file) => file.flush(),
+            Script::Buffer(buffer) => buffer.flush(),
+        }
+    }
+}
+
+impl Operations {
+    /// Returns operations which change the file system directly
+    pub fn execute() -> Operations {
+        Operations {
+            mode: Mode::Execute,
+            read_only_dirs: Vec::new(),
+        }
+    }
+
+    /// Returns operations which only describe the changes they would make
+    pub fn dry_run() -> Operations {
+        Operations {
+            mode: Mode::DryRun,
+            read_only_dirs: Vec::new(),
+        }
+    }
+
+    /// Returns operations which record each change as a step of a plan, without making it
+    pub fn plan() -> Operations {
+        Operations {
+            mode: Mode::Plan(Vec::new()),
+            read_only_dirs: Vec::new(),
+        }
+    }
+
+    /// Creates a script file at the given path, and returns operations which write to it.
+    ///
+    /// The script is a POSIX shell script, or a batch file on Windows.
+    pub fn emit_script(path: &Path) -> Result<Operations> {
+        let mut file = File::create(path)
+            .with_context(|| format!("Failed to create script file {:?}", path))?;
+
+        if cfg!(windows) {
+            write!(file, "@echo off\r\nrem Generated by lnshot\r\n")?;
+        } else {
+            writeln!(file, "#!/bin/sh\n# Generated by lnshot\nset -e")?;
+
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                file.set_permissions(std::fs::Permissions::from_mode(0o755))?;
+            }
+        }
+
+        Ok(Operations {
+            mode: Mode::EmitScript(Script::File(file)),
+            read_only_dirs: Vec::new(),
+        })
+    }
+
+    /// Returns operations which make changes the same way, for use on another thread.
+    /// Any script commands are kept until they're joined back with [`Operations::join`].
+    pub fn fork(&self) -> Operations {
+        Operations {
+            mode: match self.mode {
+                Mode::Execute => Mode::Execute,
+                Mode::EmitScript(_) => Mode::EmitScript(Script::Buffer(Vec::new())),
+                Mode::DryRun => Mode::DryRun,
+                Mode::Plan(_) => Mode::Plan(Vec::new()),
+            },
+            read_only_dirs: self.read_only_dirs.clone(),
+        }
+    }
+
+    /// Adds the script commands or planned steps of operations made with [`Operations::fork`] to these
+    pub fn join(&mut self, other: Operations) -> std::io::Result<()> {
+        match (&mut self.mode, other.mode) {
+            (Mode::EmitScript(script), Mode::EmitScript(Script::Buffer(buffer))) => {
+                script.write_all(&buffer)
+            }
+            (Mode::Plan(steps), Mode::Plan(other_steps)) => {
+                steps.extend(other_steps);
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Takes the steps recorded so far, when making a plan
+    pub fn take_steps(&mut self) -> Vec<Step> {
+        match &mut self.mode {
+            Mode::Plan(steps) => std::mem::take(steps),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Marks a directory as read-only, so any operation which would change something inside it fails
+    pub fn protect(&mut self, dir: &Path) {
+        if let Ok(canonical_dir) = dir.canonicalize() {
+            self.read_only_dirs.push(canonical_dir);
+        }
+
+        self.read_only_dirs.push(dir.to_path_buf());
+    }
+
+    /// Checks whether a path lies inside any read-only directory.
+    ///
+    /// The path itself isn't resolved, so a symlink pointing into a read-only
+    /// directory can still be created or removed.
+    pub fn is_protected(&self, path: &Path) -> bool {
+        let resolved = resolve_parent(path);
+
+        self.read_only_dirs.iter().any(|read_only_dir| {
+            path.starts_with(read_only_dir) || resolved.starts_with(read_only_dir)
+        })
+    }
+
+    /// Fails if the path lies inside a read-only directory
+    fn check_writable(&self, path: &Path) -> std::io::Result<()> {
+        if self.is_protected(path) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                format!(
+                    "Refusing to change {:?}, as it is inside a read-only directory",
+                    path
+                ),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Creates a directory and any missing parents
+    pub fn create_dir_all(&mut self, path: &Path) -> std::io::Result<()> {
+        self.check_writable(path)?;
+
+        match &mut self.mode {
+            Mode::Execute => std::fs::create_dir_all(extended_length_path(path)),
+            Mode::Plan(steps) => {
+                steps.push(Step::CreateDirAll {
+                    path: path.to_path_buf(),
+                });
+                Ok(())
+            }
+            Mode::DryRun => {
+                Event::info("dry_run", format!("Would create directory {:?}", path))
+                    .path(path)
+                    .log();
+                Ok(())
+            }
+            Mode::EmitScript(file) => {
+                let path = quote(path);
+
+                if cfg!(windows) {
+                    write!(file, "if not exist {0} mkdir {0}\r\n", path)
+                } else {
+                    writeln!(file, "mkdir -p {}", path)
+                }
+            }
+        }
+    }
+
+    /// Creates a single directory
+    pub fn create_dir(&mut self, path: &Path) -> std::io::Result<()> {
+        self.check_writable(path)?;
+
+        match &mut self.mode {
+            Mode::Execute => std::fs::create_dir(extended_length_path(path)),
+            // `create_dir_all` is equivalent here, and is idempotent
+            Mode::EmitScript(_) | Mode::DryRun => self.create_dir_all(path),
+            Mode::Plan(steps) => {
+                steps.push(Step::CreateDir {
+                    path: path.to_path_buf(),
+                });
+                Ok(())
+            }
+        }
+    }
+
+    /// Removes an empty directory
+    pub fn remove_dir(&mut self, path: &Path) -> std::io::Result<()> {
+        self.check_writable(path)?;
+
+        match &mut self.mode {
+            Mode::Execute => std::fs::remove_dir(extended_length_path(path)),
+            Mode::Plan(steps) => {
+                steps.push(Step::RemoveDir {
+                    path: path.to_path_buf(),
+                });
+                Ok(())
+            }
+            Mode::DryRun => {
+                Event::info("dry_run", format!("Would remove directory {:?}", path))
+                    .path(path)
+                    .log();
+                Ok(())
+            }
+            Mode::EmitScript(file) => {
+                let path = quote(path);
+
+                if cfg!(windows) {
+                    write!(file, "if exist {0} rmdir {0}\r\n", path)
+                } else {
+                    writeln!(
+                        file,
+                        "if [ -d {0} ] && [ ! -L {0} ]; then rmdir {0}; fi",
+                        path
+                    )
+                }
+            }
+        }
+    }
+
+    /// Removes a symlink, leaving whatever it points to alone
+    pub fn remove_symlink(&mut self, path: &Path) -> std::io::Result<()> {
+        self.check_writable(path)?;
+
+        match &mut self.mode {
+            Mode::Execute => retry(path, || {
+                symlink::remove_symlink_auto(extended_length_path(path))
+            }),
+            Mode::Plan(steps) => {
+                steps.push(Step::RemoveSymlink {
+                    path: path.to_path_buf(),
+                });
+                Ok(())
+            }
+            Mode::DryRun => {
+                Event::info("dry_run", format!("Would remove symlink {:?}", path))
+                    .path(path)
+                    .log();
+                Ok(())
+            }
+            Mode::EmitScript(file) => {
+                let path = quote(path);
+
+                if cfg!(windows) {
+                    // `rmdir` removes directory symlinks without touching their targets
+                    write!(file, "if exist {0} rmdir {0}\r\n", path)
+                } else {
+                    writeln!(file, "if [ -L {0} ]; then rm {0}; fi", path)
+                }
+            }
+        }
+    }
+
+    /// Removes a Finder alias file, leaving whatever it points to alone
+    pub fn remove_alias(&mut self, path: &Path) -> std::io::Result<()> {
+        self.check_writable(path)?;
+
+        match &mut self.mode {
+            Mode::Execute => retry(path, || std::fs::remove_file(extended_length_path(path))),
+            Mode::Plan(steps) => {
+                steps.push(Step::RemoveAlias {
+                    path: path.to_path_buf(),
+                });
+                Ok(())
+            }
+            Mode::DryRun => {
+                Event::info("dry_run", format!("Would remove alias {:?}", path))
+                    .path(path)
+                    .log();
+                Ok(())
+            }
+            Mode::EmitScript(file) => {
+                writeln!(file, "if [ -f {0} ]; then rm {0}; fi", quote(path))
+            }
+        }
+    }
+
+    /// Copies a file, keeping its modification time so later changes can be detected
+    pub fn copy_file(&mut self, source: &Path, destination: &Path) -> std::io::Result<()> {
+        self.check_writable(destination)?;
+
+        match &mut self.mode {
+            Mode::Execute => {
+                let (source, destination) = (
+                    extended_length_path(source),
+                    extended_length_path(destination),
+                );
+
+                std::fs::copy(&source, &destination)?;
+
+                let modified = std::fs::metadata(&source)?.modified()?;
+                File::options()
+                    .write(true)
+                    .open(&destination)?
+                    .set_modified(modified)
+            }
+            Mode::Plan(steps) => {
+                steps.push(Step::CopyFile {
+                    source: source.to_path_buf(),
+                    destination: destination.to_path_buf(),
+                });
+                Ok(())
+            }
+            Mode::DryRun => {
+                Event::info(
+                    "dry_run",
+                    format!("Would copy {:?} to {:?}", source, destination),
+                )
+                .path(source)
+                .path(destination)
+                .log();
+                Ok(())
+            }
+            Mode::EmitScript(file) => {
+                let (source, destination) = (quote(source), quote(destination));
+
+                if cfg!(windows) {
+                    write!(file, "copy /Y {} {} >nul\r\n", source, destination)
+                } else {
+                    writeln!(file, "cp -p {} {}", source, destination)
+                }
+            }
+        }
+    }
+
+    /// Moves a file or folder to a new path, which mustn't exist yet
+    pub fn rename(&mut self, from: &Path, to: &Path) -> std::io::Result<()> {
+        self.check_writable(from)?;
+        self.check_writable(to)?;
+
+        match &mut self.mode {
+            Mode::Execute => std::fs::rename(extended_length_path(from), extended_length_path(to)),
+            Mode::Plan(steps) => {
+                steps.push(Step::Rename {
+                    from: from.to_path_buf(),
+                    to: to.to_path_buf(),
+                });
+                Ok(())
+            }
+            Mode::DryRun => {
+                Event::info("dry_run", format!("Would rename {:?} to {:?}", from, to))
+                    .path(from)
+                    .path(to)
+                    .log();
+                Ok(())
+            }
+            Mode::EmitScript(file) => {
+                let (from, to) = (quote(from), quote(to));
+
+                if cfg!(windows) {
+                    write!(
+                        file,
+                        "if exist {0} if not exist {1} move {0} {1} >nul\r\n",
+                        from, to
+                    )
+                } else {
+                    writeln!(
+                        file,
+                        "if [ -e {0} ] && [ ! -e {1} ]; then mv {0} {1}; fi",
+                        from, to
+                    )
+                }
+            }
+        }
+    }
+
+    /// Writes a text file, replacing it if it already exists
+    pub fn write_file(&mut self, path: &Path, contents: &str) -> std::io::Result<()> {
+        self.check_writable(path)?;
+
+        match &mut self.mode {
+            Mode::Execute => std::fs::write(extended_length_path(path), contents),
+            Mode::Plan(steps) => {
+                steps.push(Step::WriteFile {
+                    path: path.to_path_buf(),
+                    contents: contents.to_string(),
+                });
+                Ok(())
+            }
+            Mode::DryRun => {
+                Event::info("dry_run", format!("Would write {:?}", path))
+                    .path(path)
+                    .log();
+                Ok(())
+            }
+            Mode::EmitScript(file) => {
+                let path = quote(path);
+
+                if cfg!(windows) {
+                    write!(file, "type nul > {}\r\n", path)?;
+
+                    for line in contents.lines() {
+                        write!(file, "echo({}>> {}\r\n", escape_batch_echo(line), path)?;
+                    }
+
+                    Ok(())
+                } else {
+                    writeln!(file, "cat > {} <<'LNSHOT_EOF'", path)?;
+                    write!(file, "{}", contents)?;
+
+                    if !contents.ends_with('\n') {
+                        writeln!(file)?;
+                    }
+
+                    writeln!(file, "LNSHOT_EOF")
+                }
+            }
+        }
+    }
+
+    /// Creates a symlink at `link` which points to the directory at `target`
+    pub fn symlink_dir(&mut self, target: &Path, link: &Path) -> std::io::Result<()> {
+        self.check_writable(link)?;
+
+        match &mut self.mode {
+            Mode::Execute => retry(link, || {
+                symlink::symlink_dir(target, extended_length_path(link))
+            }),
+            Mode::Plan(steps) => {
+                steps.push(Step::SymlinkDir {
+                    target: target.to_path_buf(),
+                    link: link.to_path_buf(),
+                });
+                Ok(())
+            }
+            Mode::DryRun => {
+                Event::info(
+                    "dry_run",
+                    format!("Would symlink {:?} to {:?}", link, target),
+                )
+                .path(link)
+                .path(target)
+                .log();
+                Ok(())
+            }
+            Mode::EmitScript(file) => {
+                let (target, link) = (quote(target), quote(link));
+
+                if cfg!(windows) {
+                    write!(file, "if not exist {0} mklink /D {0} {1}\r\n", link, target)
+                } else {
+                    writeln!(
+                        file,
+                        "if [ ! -e {0} ] && [ ! -L {0} ]; then ln -s {1} {0}; fi",
+                        link, target
+                    )
+                }
+            }
+        }
+    }
+
+    /// Makes a directory junction, which works like a symlink to a folder on Windows,
+    /// but can be made without Developer Mode or administrator rights. The target must be absolute.
+    pub fn junction_dir(&mut self, target: &Path, link: &Path) -> std::io::Result<()> {
+        self.check_writable(link)?;
+
+        match &mut self.mode {
+            Mode::Execute => {
+                let output = Command::new("cmd")
+                    .args(["/C", "mklink", "/J"])
+                    .arg(link)
+                    .arg(target)
+                    .output()?;
+
+                if !output.status.success() {
+                    return Err(std::io::Error::other(format!(
+                        "mklink exited with {}: {}",
+                        output.status,
+                        String::from_utf8_lossy(&output.stderr).trim()
+                    )));
+                }
+
+                Ok(())
+            }
+            Mode::Plan(steps) => {
+                steps.push(Step::JunctionDir {
+                    target: target.to_path_buf(),
+                    link: link.to_path_buf(),
+                });
+                Ok(())
+            }
+            Mode::DryRun => {
+                Event::info(
+                    "dry_run",
+                    format!("Would make junction {:?} to {:?}", link, target),
+                )
+                .path(link)
+                .path(target)
+                .log();
+                Ok(())
+            }
+            Mode::EmitScript(file) => {
+                write!(
+                    file,
+                    "if not exist {0} mklink /J {0} {1}\r\n",
+                    quote(link),
+                    quote(target)
+                )
+            }
+        }
+    }
+
+    /// Makes a Finder alias to a folder on macOS, by asking Finder to make it.
+    /// Unlike symlinks, aliases keep working if the folder is moved, and some apps only follow aliases.
+    pub fn alias_dir(&mut self, target: &Path, link: &Path) -> std::io::Result<()> {
+        self.check_writable(link)?;
+
+        let (Some(link_dir), Some(link_name)) = (link.parent(), link.file_name()) else {
+            return Err(std::io::Error::other(format!(
+                "{:?} can't be made into an alias",
+                link
+            )));
+        };
+
+        let script = format!(
+            "tell application \"Finder\" to make new alias file at (POSIX file {}) to (POSIX file {}) with properties {{name:{}}}",
+            applescript_string(&link_dir.to_string_lossy()),
+            applescript_string(&target.to_string_lossy()),
+            applescript_string(&link_name.to_string_lossy())
+        );
+
+        match &mut self.mode {
+            Mode::Execute => {
+                let output = Command::new("osascript").arg("-e").arg(&script).output()?;
+
+                if !output.status.success() {
+                    return Err(std::io::Error::other(format!(
+                        "osascript exited with {}: {}",
+                        output.status,
+                        String::from_utf8_lossy(&output.stderr).trim()
+                    )));
+                }
+
+                Ok(())
+            }
+            Mode::Plan(steps) => {
+                steps.push(Step::AliasDir {
+                    target: target.to_path_buf(),
+                    link: link.to_path_buf(),
+                });
+                Ok(())
+            }
+            Mode::DryRun => {
+                Event::info(
+                    "dry_run",
+                    format!("Would make alias {:?} to {:?}", link, target),
+                )
+                .path(link)
+                .path(target)
+                .log();
+                Ok(())
+            }
+            Mode::EmitScript(file) => writeln!(
+                file,
+                "if [ ! -e {0} ]; then osascript -e {1}; fi",
+                quote(link),
+                quote(Path::new(&script))
+            ),
+        }
+    }
+
+    /// Points an existing directory symlink somewhere else, without it ever being missing.
+    /// A new symlink is made alongside the old one, then renamed over it.
+    ///
+    /// Windows can't rename a symlink over another, so there the old symlink is removed first.
+    pub fn replace_symlink_dir(&mut self, target: &Path, link: &Path) -> std::io::Result<()> {
+        self.check_writable(link)?;
+
+        let temporary_link = extended_length_path(&temporary_path(link));
+
+        match &mut self.mode {
+            Mode::Execute => {
+                let link = extended_length_path(link);
+
+                if cfg!(windows) {
+                    retry(&link, || symlink::remove_symlink_auto(&link))?;
+                    return retry(&link, || symlink::symlink_dir(target, &link));
+                }
+
+                // Clear out anything left behind by an earlier run which was interrupted
+                if temporary_link.is_symlink() {
+                    retry(&temporary_link, || {
+                        symlink::remove_symlink_auto(&temporary_link)
+                    })?;
+                }
+
+                retry(&temporary_link, || {
+                    symlink::symlink_dir(target, &temporary_link)
+                })?;
+                retry(&link, || std::fs::rename(&temporary_link, &link))
+            }
+            Mode::Plan(steps) => {
+                steps.push(Step::ReplaceSymlinkDir {
+                    target: target.to_path_buf(),
+                    link: link.to_path_buf(),
+                });
+                Ok(())
+            }
+            Mode::DryRun => {
+                Event::info(
+                    "dry_run",
+                    format!("Would replace symlink {:?} with one to {:?}", link, target),
+                )
+                .path(link)
+                .path(target)
+                .log();
+                Ok(())
+            }
+            Mode::EmitScript(file) => {
+                let (target, link, temporary_link) =
+                    (quote(target), quote(link), quote(&temporary_link));
+
+                if cfg!(windows) {
+                    write!(
+                        file,
+                        "if exist {0} rmdir {0}\r\nmklink /D {0} {1}\r\n",
+                        link, target
+                    )
+                } else {
+                    // Without `-T` (or `-h` on macOS), `mv` would move the new link inside the old one's target
+                    let no_target_directory = if cfg!(target_os = "macos") {
+                        "-h"
+                    } else {
+                        "-T"
+                    };
+
+                    writeln!(
+                        file,
+                        "rm -f {2}; ln -s {1} {2} && mv -f {3} {2} {0}",
+                        link, target, temporary_link, no_target_directory
+                    )
+                }
+            }
+        }
+    }
+}
+
+/// Makes a change to the file system, trying it again a few times, waiting longer each time,
+/// if it fails with an error which may go away by itself
+fn retry<T>(path: &Path, mut change: impl FnMut() -> std::io::Result<T>) -> std::io::Result<T> {
+    let mut delay = RETRY_DELAY;
+
+    for attempt in 1..=RETRIES {
+        match change() {
+            Err(error) if is_transient(&error) => {
+                Event::warn(
+                    "retry",
+                    format!(
+                        "Failed to change {:?}, trying again in {}ms ({} of {}): {}",
+                        path,
+                        delay.as_millis(),
+                        attempt,
+                        RETRIES,
+                        error
+                    ),
+                )
+                .path(path)
+                .log();
+
+                std::thread::sleep(delay);
+                delay *= 2;
+            }
+            result => return result,
+        }
+    }
+
+    change()
+}
+
+/// Whether an error may go away by itself, such as a file briefly being locked
+fn is_transient(error: &std::io::Error) -> bool {
+    use std::io::ErrorKind;
+
+    if matches!(
+        error.kind(),
+        ErrorKind::Interrupted
+            | ErrorKind::WouldBlock
+            | ErrorKind::TimedOut
+            | ErrorKind::ResourceBusy
+            | ErrorKind::StaleNetworkFileHandle
+    ) {
+        return true;
+    }
+
+    // Antivirus and indexing tools hold files open briefly, which shows up as sharing and lock violations.
+    // Access being denied isn't retried, as it far more often means the user can't make the change at all.
+    #[cfg(windows)]
+    {
+        use windows_sys::Win32::Foundation::{ERROR_LOCK_VIOLATION, ERROR_SHARING_VIOLATION};
+
+        if let Some(code) = error.raw_os_error() {
+            return [ERROR_LOCK_VIOLATION, ERROR_SHARING_VIOLATION].contains(&(code as u32));
+        }
+    }
+
+    // Network file systems report a server which stopped responding for a moment as an I/O error
+    #[cfg(unix)]
+    {
+        if error.raw_os_error() == Some(libc::EIO) {
+            return true;
+        }
+    }
+
+    false
+}
+
+/// Converts an absolute path on Windows into an extended-length path starting with `\\?\`,
+/// so it isn't limited to `MAX_PATH` characters. Other paths are returned as they are.
+///
+/// Symlink targets are left alone, as Windows resolves them without the limit.
+pub fn extended_length_path(path: &Path) -> PathBuf {
+    if !cfg!(windows) {
+        return path.to_path_buf();
+    }
+
+    let mut components = path.components();
+
+    let Some(Component::Prefix(prefix)) = components.next() else {
+        return path.to_path_buf();
+    };
+
+    let mut extended_path = OsString::from(r"\\?\");
+
+    match prefix.kind() {
+        Prefix::Disk(_) => extended_path.push(prefix.as_os_str()),
+        Prefix::UNC(server, share) => {
+            extended_path.push(r"UNC\");
+            extended_path.push(server);
+            extended_path.push(r"\");
+            extended_path.push(share);
+        }
+        // Paths which are already extended-length, or which name devices, can't be converted
+        _ => return path.to_path_buf(),
+    }
+
+    // Extended-length paths are used exactly as given, so they can't have `.` or `..` in them
+    for component in components {
+        match component {
+            Component::RootDir | Component::CurDir => {}
+            Component::Normal(name) => {
+                extended_path.push(r"\");
+                extended_path.push(name);
+            }
+            Component::ParentDir | Component::Prefix(_) => return path.to_path_buf(),
+        }
+    }
+
+    PathBuf::from(extended_path)
+}
+
+/// Works out where to put a temporary file while making the given path,
+/// alongside it so it can be renamed into place
+fn temporary_path(path: &Path) -> PathBuf {
+    let mut file_name = OsString::from(".");
+    file_name.push(path.file_name().unwrap_or_default());
+    file_name.push(".lnshot-tmp");
+
+    path.with_file_name(file_name)
+}
+
+/// Resolves symlinks in all but the last component of a path, even if it doesn't exist yet
+fn resolve_parent(path: &Path) -> PathBuf {
+    let (Some(parent), Some(file_name)) = (path.parent(), path.file_name()) else {
+        return path.to_path_buf();
+    };
+
+    match parent.canonicalize() {
+        Ok(parent) => parent.join(file_name),
+        Err(_) => resolve_parent(parent).join(file_name),
+    }
+}
+
+/// Quotes text as an AppleScript string literal
+fn applescript_string(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Quotes a path for use as a single argument in the script
+fn quote(path: &Path) -> String {
+    if cfg!(windows) {
+        quote_batch(path)
+    } else {
+        quote_posix(path)
+    }
+}
+
+/// Quotes a path for a shell script. Nothing inside single quotes is special, except a single quote itself.
+fn quote_posix(path: &Path) -> String {
+    format!("'{}'", path.to_string_lossy().replace('\'', r"'\''"))
+}
+
+/// Quotes a path for a batch script. Paths on Windows can't contain double quotes,
+/// but batch scripts expand `%` even inside them.
+fn quote_batch(path: &Path) -> String {
+    format!("\"{}\"", path.to_string_lossy().replace('%', "%%"))
+}
+
+/// Escapes a line so a batch script's `echo` writes it exactly. Characters `echo` would otherwise
+/// treat specially are escaped with carets, except inside double quotes, where carets would be written too.
+fn escape_batch_echo(line: &str) -> String {
+    let mut escaped = String::with_capacity(line.len());
+    let mut quoted = false;
+
+    for character in line.chars() {
+        match character {
+            '"' => quoted = !quoted,
+            // Batch scripts expand `%` before anything else, even inside quotes
+            '%' => escaped.push('%'),
+            '^' | '&' | '<' | '>' | '|' if !quoted => escaped.push('^'),
+            _ => {}
+        }
+
+        escaped.push(character);
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quotes_paths_for_shell_scripts() {
+        assert_eq!(
+            quote_posix(Path::new("/home/tester/My Pictures")),
+            "'/home/tester/My Pictures'"
+        );
+        assert_eq!(
+            quote_posix(Path::new("/games/Tom Clancy's \"Game\"")),
+            r#"'/games/Tom Clancy'\''s "Game"'"#
+        );
+        assert_eq!(
+            quote_posix(Path::new("/games/$HOME 100% `done`")),
+            "'/games/$HOME 100% `done`'"
+        );
+    }
+
+    #[test]
+    fn quotes_paths_for_batch_scripts() {
+        assert_eq!(
+            quote_batch(Path::new(r"C:\Users\Tester\My Pictures")),
+            r#""C:\Users\Tester\My Pictures""#
+        );
+        assert_eq!(
+            quote_batch(Path::new(r"C:\Games\Tom Clancy's 100% $Game")),
+            r#""C:\Games\Tom Clancy's 100%% $Game""#
+        );
+        assert_eq!(
+            quote_batch(Path::new(r"C:\Games\%PATH%")),
+            r#""C:\Games\%%PATH%%""#
+        );
+    }
+
+    #[test]
+    fn escapes_lines_for_batch_echo() {
+        assert_eq!(escape_batch_echo("Portal 2"), "Portal 2");
+        assert_eq!(
+            escape_batch_echo("Rock & Roll <100%> | $HOME ^_^"),
+            "Rock ^& Roll ^<100%%^> ^| $HOME ^^_^^"
+        );
+        assert_eq!(escape_batch_echo("it's"), "it's");
+    }
+
+    #[test]
+    fn leaves_quoted_text_unescaped_for_batch_echo() {
+        assert_eq!(
+            escape_batch_echo(r#"  "Rock & Roll": "100% <done>","#),
+            r#"  "Rock & Roll": "100%% <done>","#
+        );
+        assert_eq!(escape_batch_echo(r#""a" & "b|c""#), r#""a" ^& "b|c""#);
+    }
+}
@@ -0,0 +1,46 @@
+//! Spreading work across threads, for when there's lots of it which can be done side by side
+
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+
+/// How many threads to work on by default: one for each CPU
+pub fn default_jobs() -> usize {
+    std::thread::available_parallelism().map_or(1, NonZeroUsize::get)
+}
+
+/// Does the work for each item on up to `jobs` threads at once, returning the results in the items' order.
+/// With only one job, or one item, everything is done on this thread.
+pub fn map<T: Send, R: Send>(items: Vec<T>, jobs: usize, work: impl Fn(T) -> R + Sync) -> Vec<R> {
+    if jobs <= 1 || items.len() <= 1 {
+        return items.into_iter().map(work).collect();
+    }
+
+    let count = items.len();
+    let queue = Mutex::new(items.into_iter().enumerate());
+    let results = Mutex::new((0..count).map(|_| None).collect::<Vec<Option<R>>>());
+
+    std::thread::scope(|scope| {
+        for _ in 0..jobs.min(count) {
+            scope.spawn(|| loop {
+                // The queue is only locked long enough to take the next item
+                let next = queue
+                    .lock()
+                    .unwrap_or_else(|error| error.into_inner())
+                    .next();
+                let Some((index, item)) = next else {
+                    break;
+                };
+
+                let result = work(item);
+                results.lock().unwrap_or_else(|error| error.into_inner())[index] = Some(result);
+            });
+        }
+    });
+
+    results
+        .into_inner()
+        .unwrap_or_else(|error| error.into_inner())
+        .into_iter()
+        .map(|result| result.expect("every item is worked on before the threads finish"))
+        .collect()
+}
@@ -0,0 +1,154 @@
+//! Making sure only one daemon runs at a time, by recording its process ID in a file
+
+use crate::log::Event;
+use anyhow::{Context, Result};
+use directories::ProjectDirs;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Held by the running daemon, removing its PID file once it stops
+#[derive(Debug)]
+pub struct PidFile {
+    path: PathBuf,
+}
+
+impl PidFile {
+    /// Where the daemon's PID file is kept, such as `/run/user/1000/lnshot/daemon.pid` on Linux,
+    /// or lnshot's cache folder on systems without a runtime folder
+    pub fn default_path() -> Option<PathBuf> {
+        ProjectDirs::from_path(PathBuf::from("lnshot")).map(|project_dirs| {
+            project_dirs
+                .runtime_dir()
+                .unwrap_or(project_dirs.cache_dir())
+                .join("daemon.pid")
+        })
+    }
+
+    /// Records this process in the PID file, refusing if another daemon which is still running already has.
+    /// A file left behind by a daemon which has since stopped is replaced, as is any file at all if `force` is set.
+    pub fn acquire(path: &Path, force: bool) -> Result<PidFile> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory {:?}", parent))?;
+        }
+
+        if let Some(pid) = read_pid(path) {
+            if force {
+                Event::warn(
+                    "pid_file",
+                    format!("Replacing the PID file for process {}", pid),
+                )
+                .path(path)
+                .log();
+            } else if pid != std::process::id() && is_running(pid) {
+                anyhow::bail!(
+                    "Another lnshot daemon is already running as process {}; \
+                    use --force if it isn't really running, or delete {:?}",
+                    pid,
+                    path
+                );
+            } else {
+                Event::info(
+                    "pid_file",
+                    format!(
+                        "Replacing the PID file left by process {}, which has stopped",
+                        pid
+                    ),
+                )
+                .path(path)
+                .log();
+            }
+
+            std::fs::remove_file(path)
+                .with_context(|| format!("Failed to remove PID file {:?}", path))?;
+        } else if path.exists() {
+            // Without a process ID, nothing can be running as it, such as after a crash while it was written
+            Event::warn(
+                "pid_file",
+                "Replacing the PID file, which doesn't hold a process ID",
+            )
+            .path(path)
+            .log();
+
+            std::fs::remove_file(path)
+                .with_context(|| format!("Failed to remove stale PID file {:?}", path))?;
+        }
+
+        // Creating the file only if it doesn't exist stops two daemons starting at once both succeeding
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(path)
+            .with_context(|| format!("Failed to create PID file {:?}", path))?;
+
+        writeln!(file, "{}", std::process::id())
+            .with_context(|| format!("Failed to write PID file {:?}", path))?;
+
+        Ok(PidFile {
+            path: path.to_path_buf(),
+        })
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        // Leave the file alone if another daemon was forced to start in the meantime
+        if read_pid(&self.path) == Some(std::process::id()) {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Reads the process ID recorded in a PID file, if there is one.
+/// No process has the ID 0, which would otherwise check every process in the group at once.
+pub fn read_pid(path: &Path) -> Option<u32> {
+    std::fs::read_to_string(path)
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+        .filter(|pid| *pid != 0)
+}
+
+/// Checks whether a process is running
+#[cfg(unix)]
+pub fn is_running(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+
+    // Sending no signal at all only checks whether the process could be signalled
+    let signalled = unsafe { libc::kill(pid, 0) } == 0;
+
+    signalled || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// Checks whether a process is running
+#[cfg(windows)]
+pub fn is_running(pid: u32) -> bool {
+    use windows_sys::Win32::Foundation::{CloseHandle, STILL_ACTIVE};
+    use windows_sys::Win32::System::Threading::{
+        GetExitCodeProcess, OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION,
+    };
+
+    unsafe {
+        let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+        if process.is_null() {
+            return false;
+        }
+
+        let mut exit_code = 0;
+        let running =
+            GetExitCodeProcess(process, &mut exit_code) != 0 && exit_code == STILL_ACTIVE as u32;
+        CloseHandle(process);
+
+        running
+    }
+}
+
+/// Processes can't be checked on this platform, so they're assumed to still be running
+#[cfg(not(any(unix, windows)))]
+pub fn is_running(_pid: u32) -> bool {
+    true
+}
@@ -0,0 +1,231 @@
+//! Plans of the changes `go` would make, saved so they can be reviewed before they're applied
+
+use crate::log::{self, Event};
+use crate::operations::Operations;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Version of the plan format, changed whenever older versions of lnshot couldn't apply a plan correctly
+const FORMAT_VERSION: u64 = 1;
+
+/// A single change to the file system, as made by [`Operations`].
+/// Written in plans as an object with an `action`, such as `symlink_dir`, and the paths it works with.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Step {
+    CreateDirAll {
+        path: PathBuf,
+    },
+    CreateDir {
+        path: PathBuf,
+    },
+    RemoveDir {
+        path: PathBuf,
+    },
+    RemoveSymlink {
+        path: PathBuf,
+    },
+    RemoveAlias {
+        path: PathBuf,
+    },
+    CopyFile {
+        source: PathBuf,
+        destination: PathBuf,
+    },
+    Rename {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    WriteFile {
+        path: PathBuf,
+        contents: String,
+    },
+    SymlinkDir {
+        target: PathBuf,
+        link: PathBuf,
+    },
+    JunctionDir {
+        target: PathBuf,
+        link: PathBuf,
+    },
+    AliasDir {
+        target: PathBuf,
+        link: PathBuf,
+    },
+    ReplaceSymlinkDir {
+        target: PathBuf,
+        link: PathBuf,
+    },
+}
+
+impl Step {
+    /// Makes the change with the given operations
+    pub fn apply(&self, operations: &mut Operations) -> std::io::Result<()> {
+        match self {
+            Step::CreateDirAll { path } => operations.create_dir_all(path),
+            Step::CreateDir { path } => operations.create_dir(path),
+            Step::RemoveDir { path } => operations.remove_dir(path),
+            Step::RemoveSymlink { path } => operations.remove_symlink(path),
+            Step::RemoveAlias { path } => operations.remove_alias(path),
+            Step::CopyFile {
+                source,
+                destination,
+            } => operations.copy_file(source, destination),
+            Step::Rename { from, to } => operations.rename(from, to),
+            Step::WriteFile { path, contents } => operations.write_file(path, contents),
+            Step::SymlinkDir { target, link } => operations.symlink_dir(target, link),
+            Step::JunctionDir { target, link } => operations.junction_dir(target, link),
+            Step::AliasDir { target, link } => operations.alias_dir(target, link),
+            Step::ReplaceSymlinkDir { target, link } => {
+                operations.replace_symlink_dir(target, link)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Step {
+    /// Describes the step, such as `symlink "Tester/Portal" to "/.../620/screenshots"`
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Step::CreateDirAll { path } | Step::CreateDir { path } => {
+                write!(formatter, "create directory {:?}", path)
+            }
+            Step::RemoveDir { path } => write!(formatter, "remove directory {:?}", path),
+            Step::RemoveSymlink { path } => write!(formatter, "remove symlink {:?}", path),
+            Step::RemoveAlias { path } => write!(formatter, "remove alias {:?}", path),
+            Step::CopyFile {
+                source,
+                destination,
+            } => write!(formatter, "copy {:?} to {:?}", source, destination),
+            Step::Rename { from, to } => write!(formatter, "rename {:?} to {:?}", from, to),
+            Step::WriteFile { path, .. } => write!(formatter, "write {:?}", path),
+            Step::SymlinkDir { target, link } => {
+                write!(formatter, "symlink {:?} to {:?}", link, target)
+            }
+            Step::JunctionDir { target, link } => {
+                write!(formatter, "make junction {:?} to {:?}", link, target)
+            }
+            Step::AliasDir { target, link } => {
+                write!(formatter, "make alias {:?} to {:?}", link, target)
+            }
+            Step::ReplaceSymlinkDir { target, link } => {
+                write!(
+                    formatter,
+                    "replace symlink {:?} with one to {:?}",
+                    link, target
+                )
+            }
+        }
+    }
+}
+
+/// The changes `go` would make to a managed directory, in the order it would make them
+#[derive(Debug)]
+pub struct Plan {
+    /// The managed directory the plan was made for
+    pub screenshots_dir: PathBuf,
+
+    pub steps: Vec<Step>,
+}
+
+/// A plan as it's written to a file, along with the version of lnshot which made it
+#[derive(Serialize)]
+struct PlanFile<'a> {
+    version: u64,
+    lnshot: &'a str,
+    created: String,
+    screenshots_dir: &'a Path,
+    steps: &'a [Step],
+}
+
+impl Plan {
+    /// Writes the plan to a file, as a JSON object with a `steps` array holding each change
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let contents = serde_json::to_string_pretty(&PlanFile {
+            version: FORMAT_VERSION,
+            lnshot: env!("CARGO_PKG_VERSION"),
+            created: log::timestamp(SystemTime::now()),
+            screenshots_dir: &self.screenshots_dir,
+            steps: &self.steps,
+        })
+        .with_context(|| format!("Failed to write plan to {:?}", path))?;
+
+        std::fs::write(path, contents + "\n")
+            .with_context(|| format!("Failed to write plan to {:?}", path))
+    }
+
+    /// Reads a plan from a file
+    pub fn load(path: &Path) -> Result<Plan> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read plan from {:?}", path))?;
+        let plan = serde_json::from_str::<serde_json::Value>(&contents)
+            .with_context(|| format!("Failed to parse plan {:?}", path))?;
+
+        let version = plan.get("version").and_then(serde_json::Value::as_u64);
+        if version != Some(FORMAT_VERSION) {
+            anyhow::bail!(
+                "Plan {:?} was made by a different version of lnshot ({}); make it again with `lnshot plan`",
+                path,
+                plan.get("lnshot")
+                    .and_then(serde_json::Value::as_str)
+                    .unwrap_or("unknown")
+            );
+        }
+
+        let screenshots_dir = plan
+            .get("screenshots_dir")
+            .and_then(serde_json::Value::as_str)
+            .map(PathBuf::from)
+            .with_context(|| format!("Plan {:?} doesn't say which directory it's for", path))?;
+
+        let steps = plan
+            .get("steps")
+            .and_then(serde_json::Value::as_array)
+            .with_context(|| format!("Plan {:?} has no steps", path))?
+            .iter()
+            .enumerate()
+            .map(|(index, step)| {
+                Step::deserialize(step).with_context(|| {
+                    format!("Failed to read step {} of plan {:?}", index + 1, path)
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Plan {
+            screenshots_dir,
+            steps,
+        })
+    }
+
+    /// Makes each change in turn, stopping at the first which fails, as later steps may depend on it
+    pub fn apply(&self, operations: &mut Operations) -> Result<()> {
+        Event::info(
+            "apply",
+            format!(
+                "Applying {} step(s) planned for {:?}",
+                self.steps.len(),
+                self.screenshots_dir
+            ),
+        )
+        .path(&self.screenshots_dir)
+        .log();
+
+        for (index, step) in self.steps.iter().enumerate() {
+            Event::info("apply", format!("Going to {}", step)).log();
+
+            step.apply(operations).with_context(|| {
+                format!(
+                    "Failed to {} (step {} of {}); no later steps were applied",
+                    step,
+                    index + 1,
+                    self.steps.len()
+                )
+            })?;
+        }
+
+        Ok(())
+    }
+}
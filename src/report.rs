@@ -0,0 +1,250 @@
+//! Reporting what `go`, `list`, `status`, `export` and `list-unmatched-shortcuts` found in a form
+//! other tools can read
+
+use clap::ValueEnum;
+use serde::{Serialize, Serializer};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// How results are shown
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Tables with aligned columns, for reading
+    #[default]
+    Table,
+
+    /// A JSON object with every user's apps in a `mappings` array, for other tools to read
+    Json,
+}
+
+/// How `export` writes the mapping
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    /// Comma-separated values with a header row, for spreadsheets
+    #[default]
+    Csv,
+
+    /// The same JSON object as `--output json`
+    Json,
+}
+
+/// An app's screenshot folder for a user, and what lnshot did or found there.
+/// As JSON, anything which wasn't found is left out.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Mapping {
+    pub steamid: String,
+
+    #[serde(rename = "user")]
+    pub user_name: String,
+
+    /// Missing for users without a Steam screenshot folder, who have no apps
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub appid: Option<u64>,
+
+    /// The app's name, if it's known
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    /// The app's screenshot folder inside Steam's `userdata` directory
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_path"
+    )]
+    pub source: Option<PathBuf>,
+
+    /// Where the app's folder is, or would be, in the managed directory
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_path"
+    )]
+    pub link: Option<PathBuf>,
+
+    /// What `go` did, such as `linked`, `unchanged`, `failed` or `excluded`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub action: Option<&'static str>,
+
+    /// What `status` found, such as `symlink`, `missing` or `broken_symlink`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<&'static str>,
+
+    /// How many screenshots there are, from `list`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub screenshots: Option<usize>,
+
+    pub excluded: bool,
+}
+
+/// Writes paths as strings, replacing anything which isn't valid Unicode, as they're only being reported
+fn serialize_path<S: Serializer>(path: &Option<PathBuf>, serializer: S) -> Result<S::Ok, S::Error> {
+    path.as_deref()
+        .map(Path::to_string_lossy)
+        .serialize(serializer)
+}
+
+/// Every user's apps, gathered from every Steam installation so they're printed together, once
+#[derive(Debug, Default, Serialize)]
+pub struct Report {
+    pub mappings: Vec<Mapping>,
+
+    /// App ID-named folders lnshot didn't make, whose apps now have names, as found by `status`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub renameable_folders: Option<Vec<RenameableFolder>>,
+}
+
+impl Report {
+    /// Adds what was found in another Steam installation
+    pub fn extend(&mut self, other: Report) {
+        self.mappings.extend(other.mappings);
+
+        // Installations can share the managed directory, so each folder is only listed once
+        if let Some(renameable_folders) = other.renameable_folders {
+            let known_folders = self.renameable_folders.get_or_insert_with(Vec::new);
+            for folder in renameable_folders {
+                if !known_folders.iter().any(|known| known.path == folder.path) {
+                    known_folders.push(folder);
+                }
+            }
+        }
+    }
+
+    /// Prints every mapping as a single JSON object, such as `{"mappings": [{"steamid": ...}]}`
+    pub fn print_json(&self) {
+        println!(
+            "{}",
+            serde_json::to_string(self)
+                .expect("mappings are always serialisable, as their paths are written lossily")
+        );
+    }
+
+    /// Prints every app as a row of comma-separated values, after a header row naming each column
+    pub fn print_csv(&self) {
+        println!("user,steamid,appid,name,screenshots,link");
+
+        for mapping in &self.mappings {
+            let Some(appid) = mapping.appid else {
+                continue;
+            };
+
+            println!(
+                "{},{},{},{},{},{}",
+                csv_field(&mapping.user_name),
+                csv_field(&mapping.steamid),
+                appid,
+                csv_field(mapping.name.as_deref().unwrap_or("")),
+                mapping
+                    .screenshots
+                    .map_or(String::new(), |count| count.to_string()),
+                csv_field(
+                    &mapping
+                        .link
+                        .as_ref()
+                        .map_or(String::new(), |link| link.to_string_lossy().into_owned())
+                ),
+            );
+        }
+    }
+}
+
+/// A folder named after an app's ID which lnshot left alone, and the name it could have now
+#[derive(Debug, Clone, Serialize)]
+pub struct RenameableFolder {
+    pub steamid: String,
+
+    #[serde(rename = "user")]
+    pub user_name: String,
+
+    pub appid: u64,
+
+    /// Where the folder is, written lossily like every other path
+    pub path: String,
+
+    pub name: String,
+}
+
+/// A user's screenshot folder, named by the account and app IDs it's stored under
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct ScreenshotFolder {
+    pub account_id: u64,
+    pub appid: u64,
+}
+
+impl fmt::Display for ScreenshotFolder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.account_id, self.appid)
+    }
+}
+
+/// A non-Steam shortcut, with the IDs used to match it to screenshot folders
+#[derive(Debug, Clone, Serialize)]
+pub struct ShortcutMapping {
+    pub app_name: String,
+    pub appid: u32,
+    pub masked_appid: u32,
+    pub steam_id: u64,
+    pub matched_screenshot_folders: Vec<ScreenshotFolder>,
+}
+
+/// Every non-Steam shortcut, and every screenshot folder which couldn't be named,
+/// gathered from every Steam installation so they're printed together, once
+#[derive(Debug, Default, Serialize)]
+pub struct ShortcutReport {
+    pub shortcuts: Vec<ShortcutMapping>,
+    pub unmatched_screenshot_folders: Vec<ScreenshotFolder>,
+}
+
+impl ShortcutReport {
+    /// Adds what was found in another Steam installation
+    pub fn extend(&mut self, other: ShortcutReport) {
+        self.shortcuts.extend(other.shortcuts);
+        self.unmatched_screenshot_folders
+            .extend(other.unmatched_screenshot_folders);
+    }
+
+    /// Prints each shortcut and unnamed folder on its own line
+    pub fn print_text(&self) {
+        println!("Non-Steam shortcuts:");
+        for shortcut in &self.shortcuts {
+            let matched_folders = shortcut
+                .matched_screenshot_folders
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>();
+
+            println!(
+                "  {:?}: app ID {}, masked app ID {}, Steam ID {}, screenshot folders: {}",
+                shortcut.app_name,
+                shortcut.appid,
+                shortcut.masked_appid,
+                shortcut.steam_id,
+                if matched_folders.is_empty() {
+                    "none".to_string()
+                } else {
+                    matched_folders.join(", ")
+                }
+            );
+        }
+
+        println!("Screenshot folders without a name (account ID/app ID):");
+        for folder in &self.unmatched_screenshot_folders {
+            println!("  {}", folder);
+        }
+    }
+
+    /// Prints the shortcuts and unnamed folders as a single JSON object,
+    /// such as `{"shortcuts": [...], "unmatched_screenshot_folders": [...]}`
+    pub fn print_json(&self) {
+        println!(
+            "{}",
+            serde_json::to_string(self).expect("shortcuts are always serialisable")
+        );
+    }
+}
+
+/// Quotes a field if it has anything which would otherwise split it, doubling any quotes inside
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
@@ -0,0 +1,641 @@
+//! Setting lnshot's daemon up to start automatically when the user logs in
+
+use crate::pid_file::{is_running, read_pid, PidFile};
+use anyhow::{Context, Result};
+use directories::{BaseDirs, ProjectDirs};
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Name of the systemd unit the daemon is installed as
+const SYSTEMD_UNIT_NAME: &str = "lnshot.service";
+
+/// Where the daemon's systemd user unit is written, such as `~/.config/systemd/user/lnshot.service`
+pub fn systemd_unit_path() -> Option<PathBuf> {
+    BaseDirs::new().map(|base_dirs| {
+        base_dirs
+            .config_dir()
+            .join("systemd")
+            .join("user")
+            .join(SYSTEMD_UNIT_NAME)
+    })
+}
+
+/// Label the daemon's launchd agent is installed as
+const LAUNCH_AGENT_LABEL: &str = "com.github.ticky.lnshot";
+
+/// Where the daemon's launchd agent is written, such as `~/Library/LaunchAgents/com.github.ticky.lnshot.plist`
+pub fn launch_agent_path() -> Option<PathBuf> {
+    BaseDirs::new().map(|base_dirs| {
+        base_dirs
+            .home_dir()
+            .join("Library")
+            .join("LaunchAgents")
+            .join(format!("{}.plist", LAUNCH_AGENT_LABEL))
+    })
+}
+
+/// Registry key of programs Windows starts when the user logs in
+const WINDOWS_RUN_KEY: &str = r"HKCU\Software\Microsoft\Windows\CurrentVersion\Run";
+
+/// Name of the daemon's value in the Windows `Run` key
+const WINDOWS_RUN_VALUE: &str = "lnshot";
+
+/// Name of the daemon's Windows scheduled task
+const SCHEDULED_TASK_NAME: &str = "lnshot";
+
+/// Where the daemon writes its output when running in the background on Windows,
+/// such as `%LOCALAPPDATA%\lnshot\data\lnshot.log`
+pub fn background_log_path() -> Option<PathBuf> {
+    ProjectDirs::from_path(PathBuf::from("lnshot"))
+        .map(|project_dirs| project_dirs.data_local_dir().join("lnshot.log"))
+}
+
+/// Installs and starts a service which runs lnshot with the given arguments, such as `daemon`, whenever the user is logged in.
+/// This is a systemd user unit on Linux, a launchd agent on macOS, and a startup program on Windows.
+pub fn install(arguments: &[OsString]) -> Result<()> {
+    let executable =
+        std::env::current_exe().with_context(|| "Failed to find the lnshot executable")?;
+
+    if cfg!(target_os = "macos") {
+        install_launch_agent(&executable, arguments)
+    } else if cfg!(target_os = "linux") {
+        install_systemd_unit(&executable, arguments)
+    } else if cfg!(windows) {
+        install_startup_program(&executable, arguments)
+    } else {
+        anyhow::bail!("Services can't be installed on this platform");
+    }
+}
+
+/// Stops and removes the service made by [`install`]
+pub fn uninstall() -> Result<()> {
+    if cfg!(target_os = "macos") {
+        uninstall_launch_agent()
+    } else if cfg!(target_os = "linux") {
+        uninstall_systemd_unit()
+    } else if cfg!(windows) {
+        uninstall_startup_program()
+    } else {
+        anyhow::bail!("Services can't be installed on this platform");
+    }
+}
+
+/// Carries on without a console window, writing output to the given log file instead.
+/// Only Windows gives the daemon a window of its own, so nothing changes elsewhere.
+#[cfg(windows)]
+pub fn run_in_background(log_path: &Path) -> Result<()> {
+    use std::os::windows::io::IntoRawHandle;
+    use windows_sys::Win32::System::Console::{
+        FreeConsole, SetStdHandle, STD_ERROR_HANDLE, STD_OUTPUT_HANDLE,
+    };
+
+    if let Some(log_dir) = log_path.parent() {
+        std::fs::create_dir_all(log_dir)
+            .with_context(|| format!("Failed to create directory {:?}", log_dir))?;
+    }
+
+    let output_log = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_path)
+        .with_context(|| format!("Failed to open log file {:?}", log_path))?;
+    let error_log = output_log
+        .try_clone()
+        .with_context(|| format!("Failed to open log file {:?}", log_path))?;
+
+    // The standard handles are looked up each time anything is printed, so replacing them redirects all output
+    unsafe {
+        FreeConsole();
+        SetStdHandle(STD_OUTPUT_HANDLE, output_log.into_raw_handle());
+        SetStdHandle(STD_ERROR_HANDLE, error_log.into_raw_handle());
+    }
+
+    Ok(())
+}
+
+/// Carries on without a console window, writing output to the given log file instead.
+/// Only Windows gives the daemon a window of its own, so nothing changes elsewhere.
+#[cfg(not(windows))]
+pub fn run_in_background(_log_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+fn install_systemd_unit(executable: &Path, arguments: &[OsString]) -> Result<()> {
+    let unit_path = systemd_unit_path().with_context(|| "Failed to find the config folder")?;
+    write_service_file(&unit_path, &systemd_unit(executable, arguments))?;
+    println!("Wrote systemd unit {:?}", unit_path);
+
+    systemctl(&["daemon-reload"])?;
+    systemctl(&["enable", "--now", SYSTEMD_UNIT_NAME])?;
+
+    println!(
+        "Enabled and started {}; check on it with `systemctl --user status {}`",
+        SYSTEMD_UNIT_NAME, SYSTEMD_UNIT_NAME
+    );
+    Ok(())
+}
+
+fn uninstall_systemd_unit() -> Result<()> {
+    let unit_path = systemd_unit_path().with_context(|| "Failed to find the config folder")?;
+
+    if !unit_path.exists() {
+        println!("No systemd unit is installed at {:?}", unit_path);
+        return Ok(());
+    }
+
+    systemctl(&["disable", "--now", SYSTEMD_UNIT_NAME])?;
+
+    std::fs::remove_file(&unit_path)
+        .with_context(|| format!("Failed to remove {:?}", unit_path))?;
+    println!("Removed systemd unit {:?}", unit_path);
+
+    systemctl(&["daemon-reload"])
+}
+
+fn install_launch_agent(executable: &Path, arguments: &[OsString]) -> Result<()> {
+    let base_dirs = BaseDirs::new().with_context(|| "Failed to find the home folder")?;
+    let log_path = base_dirs
+        .home_dir()
+        .join("Library")
+        .join("Logs")
+        .join("lnshot.log");
+
+    let agent_path = launch_agent_path().with_context(|| "Failed to find the home folder")?;
+
+    // An agent which is already loaded keeps running its old settings until it's unloaded
+    if agent_path.exists() {
+        let _ = launchctl(&["unload".as_ref(), agent_path.as_os_str()]);
+    }
+
+    write_service_file(&agent_path, &launch_agent(executable, arguments, &log_path))?;
+    println!("Wrote launchd agent {:?}", agent_path);
+
+    launchctl(&["load".as_ref(), "-w".as_ref(), agent_path.as_os_str()])?;
+
+    println!(
+        "Loaded {}; its output is written to {:?}",
+        LAUNCH_AGENT_LABEL, log_path
+    );
+    Ok(())
+}
+
+fn uninstall_launch_agent() -> Result<()> {
+    let agent_path = launch_agent_path().with_context(|| "Failed to find the home folder")?;
+
+    if !agent_path.exists() {
+        println!("No launchd agent is installed at {:?}", agent_path);
+        return Ok(());
+    }
+
+    launchctl(&["unload".as_ref(), "-w".as_ref(), agent_path.as_os_str()])?;
+
+    std::fs::remove_file(&agent_path)
+        .with_context(|| format!("Failed to remove {:?}", agent_path))?;
+    println!("Removed launchd agent {:?}", agent_path);
+
+    Ok(())
+}
+
+fn install_startup_program(executable: &Path, arguments: &[OsString]) -> Result<()> {
+    let background_arguments = arguments
+        .iter()
+        .cloned()
+        .chain(std::iter::once(OsString::from("--background")))
+        .collect::<Vec<_>>();
+
+    let command = std::iter::once(executable.as_os_str())
+        .chain(background_arguments.iter().map(OsString::as_os_str))
+        .map(|arg| format!("\"{}\"", arg.to_string_lossy()))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    run_tool(
+        "reg",
+        &[
+            "add",
+            WINDOWS_RUN_KEY,
+            "/v",
+            WINDOWS_RUN_VALUE,
+            "/t",
+            "REG_SZ",
+            "/d",
+            &command,
+            "/f",
+        ],
+    )?;
+    println!(
+        "Added lnshot to the programs started when you log in, running: {}",
+        command
+    );
+
+    // Start it now too, rather than waiting for the next login
+    let mut daemon = Command::new(executable);
+    daemon.args(&background_arguments);
+
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        use windows_sys::Win32::System::Threading::CREATE_NO_WINDOW;
+
+        daemon.creation_flags(CREATE_NO_WINDOW);
+    }
+
+    daemon
+        .spawn()
+        .with_context(|| "Failed to start the daemon")?;
+
+    if let Some(log_path) = background_log_path() {
+        println!(
+            "Started the daemon; its output is written to {:?}",
+            log_path
+        );
+    }
+    Ok(())
+}
+
+fn uninstall_startup_program() -> Result<()> {
+    run_tool(
+        "reg",
+        &["delete", WINDOWS_RUN_KEY, "/v", WINDOWS_RUN_VALUE, "/f"],
+    )?;
+    println!("Removed lnshot from the programs started when you log in");
+
+    // Without a console window, the running daemon can only be stopped forcibly
+    let running_pid = PidFile::default_path()
+        .and_then(|pid_file_path| read_pid(&pid_file_path))
+        .filter(|pid| is_running(*pid));
+
+    if let Some(pid) = running_pid {
+        run_tool("taskkill", &["/PID", &pid.to_string(), "/F"])?;
+        println!("Stopped the running daemon");
+    }
+
+    Ok(())
+}
+
+/// Runs a command line tool, failing if it does
+fn run_tool(program: &str, args: &[&str]) -> Result<()> {
+    let status = Command::new(program)
+        .args(args)
+        .status()
+        .with_context(|| format!("Failed to run {}", program))?;
+
+    if !status.success() {
+        anyhow::bail!("{} exited with {}", program, status);
+    }
+
+    Ok(())
+}
+
+/// Where the daemon's autostart entry is written, such as `~/.config/autostart/lnshot.desktop`
+pub fn autostart_entry_path() -> Option<PathBuf> {
+    BaseDirs::new().map(|base_dirs| {
+        base_dirs
+            .config_dir()
+            .join("autostart")
+            .join("lnshot.desktop")
+    })
+}
+
+/// Adds an XDG autostart entry, which desktop environments use to run lnshot with the given arguments when the user logs in
+pub fn enable_autostart(arguments: &[OsString]) -> Result<()> {
+    if cfg!(windows) || cfg!(target_os = "macos") {
+        anyhow::bail!("Autostart entries are only used by Linux desktops; use `lnshot service install` instead");
+    }
+
+    let executable =
+        std::env::current_exe().with_context(|| "Failed to find the lnshot executable")?;
+    let entry_path = autostart_entry_path().with_context(|| "Failed to find the config folder")?;
+
+    write_service_file(&entry_path, &autostart_entry(&executable, arguments))?;
+
+    println!(
+        "Wrote autostart entry {:?}; the daemon will start next time you log in",
+        entry_path
+    );
+    Ok(())
+}
+
+/// Removes the entry made by [`enable_autostart`]
+pub fn disable_autostart() -> Result<()> {
+    let entry_path = autostart_entry_path().with_context(|| "Failed to find the config folder")?;
+
+    if !entry_path.exists() {
+        println!("No autostart entry exists at {:?}", entry_path);
+        return Ok(());
+    }
+
+    std::fs::remove_file(&entry_path)
+        .with_context(|| format!("Failed to remove {:?}", entry_path))?;
+    println!("Removed autostart entry {:?}", entry_path);
+
+    Ok(())
+}
+
+/// Writes a desktop entry which runs the daemon when the user logs in, without showing up in menus
+fn autostart_entry(executable: &Path, arguments: &[OsString]) -> String {
+    let command = std::iter::once(executable.as_os_str())
+        .chain(arguments.iter().map(OsString::as_os_str))
+        .map(|arg| desktop_entry_quote(&arg.to_string_lossy()))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    format!(
+        "[Desktop Entry]\n\
+        Type=Application\n\
+        Name=lnshot\n\
+        Comment=Symlink your Steam games' screenshot folders into your Pictures folder\n\
+        Exec={}\n\
+        Terminal=false\n\
+        NoDisplay=true\n\
+        X-GNOME-Autostart-enabled=true\n",
+        command
+    )
+}
+
+/// Quotes an argument for a desktop entry's `Exec` key. Escaping backslashes are themselves escaped,
+/// as the entry unescapes its strings before splitting up the command.
+fn desktop_entry_quote(arg: &str) -> String {
+    let mut quoted = String::from("\"");
+
+    for character in arg.chars() {
+        match character {
+            '\\' => quoted.push_str("\\\\\\\\"),
+            '"' | '`' | '$' => {
+                quoted.push_str("\\\\");
+                quoted.push(character);
+            }
+            '%' => quoted.push_str("%%"),
+            _ => quoted.push(character),
+        }
+    }
+
+    quoted.push('"');
+    quoted
+}
+
+/// Registers a Windows scheduled task which runs lnshot in the background with the given arguments when the user logs in,
+/// and starts it straight away
+pub fn register_scheduled_task(arguments: &[OsString]) -> Result<()> {
+    if !cfg!(windows) {
+        anyhow::bail!(
+            "Scheduled tasks are only available on Windows; use `lnshot service install` instead"
+        );
+    }
+
+    let executable =
+        std::env::current_exe().with_context(|| "Failed to find the lnshot executable")?;
+
+    let user = match (std::env::var("USERDOMAIN"), std::env::var("USERNAME")) {
+        (Ok(domain), Ok(user_name)) => format!("{}\\{}", domain, user_name),
+        (Err(_), Ok(user_name)) => user_name,
+        _ => anyhow::bail!("Failed to find the current user's name"),
+    };
+
+    let background_arguments = arguments
+        .iter()
+        .cloned()
+        .chain(std::iter::once(OsString::from("--background")))
+        .collect::<Vec<_>>();
+
+    // Task Scheduler only reads the task's full settings from XML, which it expects in UTF-16
+    let task_path = std::env::temp_dir().join("lnshot-task.xml");
+    let task_xml = scheduled_task(&executable, &background_arguments, &user);
+    let task_bytes = std::iter::once(0xFEFF)
+        .chain(task_xml.encode_utf16())
+        .flat_map(u16::to_le_bytes)
+        .collect::<Vec<_>>();
+
+    std::fs::write(&task_path, task_bytes)
+        .with_context(|| format!("Failed to write {:?}", task_path))?;
+
+    let task_path_str = task_path.to_string_lossy();
+    let result = run_tool(
+        "schtasks",
+        &[
+            "/Create",
+            "/TN",
+            SCHEDULED_TASK_NAME,
+            "/XML",
+            &task_path_str,
+            "/F",
+        ],
+    );
+    let _ = std::fs::remove_file(&task_path);
+    result?;
+
+    println!(
+        "Registered scheduled task {:?} to run when {} logs in",
+        SCHEDULED_TASK_NAME, user
+    );
+
+    run_tool("schtasks", &["/Run", "/TN", SCHEDULED_TASK_NAME])?;
+
+    if let Some(log_path) = background_log_path() {
+        println!(
+            "Started the daemon; its output is written to {:?}",
+            log_path
+        );
+    }
+    Ok(())
+}
+
+/// Stops and removes the task registered by [`register_scheduled_task`]
+pub fn unregister_scheduled_task() -> Result<()> {
+    if !cfg!(windows) {
+        anyhow::bail!("Scheduled tasks are only available on Windows");
+    }
+
+    // The task may well not be running, which is fine
+    let _ = run_tool("schtasks", &["/End", "/TN", SCHEDULED_TASK_NAME]);
+
+    run_tool("schtasks", &["/Delete", "/TN", SCHEDULED_TASK_NAME, "/F"])?;
+    println!("Removed scheduled task {:?}", SCHEDULED_TASK_NAME);
+
+    Ok(())
+}
+
+/// Writes a Task Scheduler task which runs the daemon when the user logs in. Unlike tasks made with
+/// `schtasks` alone, it isn't stopped after three days, or when running on battery power.
+fn scheduled_task(executable: &Path, arguments: &[OsString], user: &str) -> String {
+    let arguments = arguments
+        .iter()
+        .map(|arg| format!("\"{}\"", arg.to_string_lossy()))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-16"?>
+<Task version="1.2" xmlns="http://schemas.microsoft.com/windows/2004/02/mit/task">
+  <RegistrationInfo>
+    <Description>Symlink your Steam games' screenshot folders into your Pictures folder</Description>
+  </RegistrationInfo>
+  <Triggers>
+    <LogonTrigger>
+      <Enabled>true</Enabled>
+      <UserId>{user}</UserId>
+    </LogonTrigger>
+  </Triggers>
+  <Principals>
+    <Principal id="Author">
+      <UserId>{user}</UserId>
+      <LogonType>InteractiveToken</LogonType>
+      <RunLevel>LeastPrivilege</RunLevel>
+    </Principal>
+  </Principals>
+  <Settings>
+    <MultipleInstancesPolicy>IgnoreNew</MultipleInstancesPolicy>
+    <DisallowStartIfOnBatteries>false</DisallowStartIfOnBatteries>
+    <StopIfGoingOnBatteries>false</StopIfGoingOnBatteries>
+    <ExecutionTimeLimit>PT0S</ExecutionTimeLimit>
+    <RestartOnFailure>
+      <Interval>PT1M</Interval>
+      <Count>3</Count>
+    </RestartOnFailure>
+  </Settings>
+  <Actions Context="Author">
+    <Exec>
+      <Command>{command}</Command>
+      <Arguments>{arguments}</Arguments>
+    </Exec>
+  </Actions>
+</Task>
+"#,
+        user = xml_escape(user),
+        command = xml_escape(&executable.to_string_lossy()),
+        arguments = xml_escape(&arguments),
+    )
+}
+
+/// Writes a service's file, making the folder it goes in if need be
+fn write_service_file(path: &Path, contents: &str) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create directory {:?}", dir))?;
+    }
+
+    std::fs::write(path, contents).with_context(|| format!("Failed to write {:?}", path))
+}
+
+/// Writes a launchd agent property list which runs the daemon at login, and again if it fails
+fn launch_agent(executable: &Path, arguments: &[OsString], log_path: &Path) -> String {
+    let program_arguments = std::iter::once(executable.as_os_str())
+        .chain(arguments.iter().map(OsString::as_os_str))
+        .map(|arg| {
+            format!(
+                "    <string>{}</string>\n",
+                xml_escape(&arg.to_string_lossy())
+            )
+        })
+        .collect::<String>();
+
+    let log_path = xml_escape(&log_path.to_string_lossy());
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+  <key>Label</key>
+  <string>{}</string>
+  <key>ProgramArguments</key>
+  <array>
+{}  </array>
+  <key>RunAtLoad</key>
+  <true/>
+  <key>KeepAlive</key>
+  <dict>
+    <key>SuccessfulExit</key>
+    <false/>
+  </dict>
+  <key>StandardOutPath</key>
+  <string>{}</string>
+  <key>StandardErrorPath</key>
+  <string>{}</string>
+</dict>
+</plist>
+"#,
+        LAUNCH_AGENT_LABEL, program_arguments, log_path, log_path
+    )
+}
+
+/// Escapes text for use inside an XML element
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Runs `launchctl`, which manages the user's launchd agents
+fn launchctl(args: &[&OsStr]) -> Result<()> {
+    let status = Command::new("launchctl")
+        .args(args)
+        .status()
+        .with_context(|| "Failed to run launchctl")?;
+
+    if !status.success() {
+        anyhow::bail!("launchctl exited with {}", status);
+    }
+
+    Ok(())
+}
+
+/// Writes a systemd user unit running the daemon.
+/// Reloading the unit sends `SIGHUP`, which makes the daemon read its settings again.
+/// The daemon tells systemd once it's watching for changes, and keeps pinging its watchdog
+/// while it's working, so it's restarted if it hangs.
+fn systemd_unit(executable: &Path, arguments: &[OsString]) -> String {
+    let command = std::iter::once(executable.as_os_str())
+        .chain(arguments.iter().map(OsString::as_os_str))
+        .map(|arg| systemd_quote(&arg.to_string_lossy()))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    format!(
+        "[Unit]\n\
+        Description=Steam Screenshot Symlinking Service\n\
+        \n\
+        [Service]\n\
+        Type=notify\n\
+        ExecStart={}\n\
+        ExecReload=/bin/kill -HUP $MAINPID\n\
+        Restart=always\n\
+        TimeoutStartSec=10min\n\
+        WatchdogSec=10min\n\
+        \n\
+        [Install]\n\
+        WantedBy=default.target\n",
+        command
+    )
+}
+
+/// Quotes an argument for a systemd command line, where `%` starts a specifier and `$` a variable
+fn systemd_quote(arg: &str) -> String {
+    format!(
+        "\"{}\"",
+        arg.replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('%', "%%")
+            .replace('$', "$$")
+    )
+}
+
+/// Runs `systemctl` for the user's own service manager
+fn systemctl(args: &[&str]) -> Result<()> {
+    let status = Command::new("systemctl")
+        .arg("--user")
+        .args(args)
+        .status()
+        .with_context(|| "Failed to run systemctl")?;
+
+    if !status.success() {
+        anyhow::bail!(
+            "`systemctl --user {}` exited with {}",
+            args.join(" "),
+            status
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,139 @@
+//! Noticing signals sent to the daemon, so it can act on them between changes rather than
+//! wherever it happens to be
+
+use anyhow::Result;
+use std::fmt;
+
+/// A signal the daemon acts on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signal {
+    /// Ctrl-C was pressed
+    Interrupt,
+
+    /// A service manager asked the daemon to stop
+    Terminate,
+
+    /// The daemon was asked to read its settings again
+    Hangup,
+
+    /// The daemon was asked to link everything again straight away
+    User1,
+}
+
+impl fmt::Display for Signal {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Signal::Interrupt => write!(formatter, "SIGINT"),
+            Signal::Terminate => write!(formatter, "SIGTERM"),
+            Signal::Hangup => write!(formatter, "SIGHUP"),
+            Signal::User1 => write!(formatter, "SIGUSR1"),
+        }
+    }
+}
+
+/// Passes signals to the handler as they arrive, from a thread of their own, instead of letting them
+/// stop the process. On Unix this has to happen before any other threads are started, so that they
+/// leave signals to that thread.
+#[cfg(unix)]
+pub fn forward<F>(handler: F) -> Result<()>
+where
+    F: Fn(Signal) + Send + 'static,
+{
+    use anyhow::Context;
+
+    let signals = [
+        (libc::SIGINT, Signal::Interrupt),
+        (libc::SIGTERM, Signal::Terminate),
+        (libc::SIGHUP, Signal::Hangup),
+        (libc::SIGUSR1, Signal::User1),
+    ];
+
+    // Blocked signals wait to be collected by `sigwait`, rather than being handled wherever the process is
+    let signal_set = unsafe {
+        let mut signal_set: libc::sigset_t = std::mem::zeroed();
+        libc::sigemptyset(&mut signal_set);
+
+        for (number, _signal) in signals {
+            libc::sigaddset(&mut signal_set, number);
+        }
+
+        let result = libc::pthread_sigmask(libc::SIG_BLOCK, &signal_set, std::ptr::null_mut());
+        if result != 0 {
+            return Err(std::io::Error::from_raw_os_error(result))
+                .with_context(|| "Failed to block signals");
+        }
+
+        signal_set
+    };
+
+    std::thread::Builder::new()
+        .name("signals".to_string())
+        .spawn(move || loop {
+            let mut number = 0;
+
+            if unsafe { libc::sigwait(&signal_set, &mut number) } != 0 {
+                continue;
+            }
+
+            if let Some((_number, signal)) = signals.iter().find(|(n, _signal)| *n == number) {
+                handler(*signal);
+            }
+        })
+        .with_context(|| "Failed to start the signal handling thread")?;
+
+    Ok(())
+}
+
+/// Passes Ctrl-C and Ctrl-Break to the handler as they arrive, instead of letting them stop the process
+#[cfg(windows)]
+pub fn forward<F>(handler: F) -> Result<()>
+where
+    F: Fn(Signal) + Send + 'static,
+{
+    use anyhow::Context;
+    use std::sync::Mutex;
+    use windows_sys::Win32::Foundation::BOOL;
+    use windows_sys::Win32::System::Console::{
+        SetConsoleCtrlHandler, CTRL_BREAK_EVENT, CTRL_C_EVENT,
+    };
+
+    lazy_static::lazy_static! {
+        static ref HANDLER: Mutex<Option<Box<dyn Fn(Signal) + Send>>> = Mutex::new(None);
+    }
+
+    // Windows runs this on a thread of its own, so it's free to call the handler directly
+    unsafe extern "system" fn console_handler(control_type: u32) -> BOOL {
+        let signal = match control_type {
+            CTRL_C_EVENT => Signal::Interrupt,
+            CTRL_BREAK_EVENT => Signal::Terminate,
+            _ => return 0,
+        };
+
+        match HANDLER.lock().ok().as_deref() {
+            Some(Some(handler)) => {
+                handler(signal);
+                1
+            }
+            _ => 0,
+        }
+    }
+
+    if let Ok(mut current_handler) = HANDLER.lock() {
+        *current_handler = Some(Box::new(handler));
+    }
+
+    if unsafe { SetConsoleCtrlHandler(Some(console_handler), 1) } == 0 {
+        return Err(std::io::Error::last_os_error()).with_context(|| "Failed to handle Ctrl-C");
+    }
+
+    Ok(())
+}
+
+/// Signals can't be caught on this platform, so they stop the process as usual
+#[cfg(not(any(unix, windows)))]
+pub fn forward<F>(_handler: F) -> Result<()>
+where
+    F: Fn(Signal) + Send + 'static,
+{
+    Ok(())
+}
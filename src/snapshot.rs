@@ -0,0 +1,275 @@
+use crate::log::Event;
+use crate::manifest::Manifest;
+use crate::operations::Operations;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use steamid_ng::{AccountType, Instance, SteamID, Universe};
+
+/// A record of the folders and symlinks inside the managed directory at a point in time,
+/// saved as a JSON object with an `entries` array
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Snapshot {
+    /// The managed directory the snapshot was taken of
+    root: String,
+
+    /// Everything found inside the managed directory
+    #[serde(default)]
+    entries: Vec<SnapshotEntry>,
+}
+
+/// A single folder or symlink inside the managed directory
+#[derive(Serialize, Deserialize, Debug)]
+struct SnapshotEntry {
+    /// Path relative to the managed directory
+    path: String,
+
+    kind: EntryKind,
+
+    /// Where a symlink points to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    target: Option<String>,
+
+    /// App ID of a symlink lnshot made, as recorded in the manifest
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    appid: Option<u64>,
+
+    /// SteamID64 of the user a symlink lnshot made is for, as recorded in the manifest
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    user: Option<String>,
+}
+
+/// Type of item recorded in a snapshot
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum EntryKind {
+    Directory,
+    Symlink,
+}
+
+impl Snapshot {
+    /// Records the folders and symlinks inside the managed directory, along with the app and user
+    /// of each symlink the manifest records lnshot making. Symlinks aren't followed, and other files are left out,
+    /// so snapshots are only taken of directories lnshot manages with symlinks.
+    pub fn take(root: &Path, manifest: &Manifest) -> Result<Snapshot> {
+        let mut entries = Vec::new();
+        collect_entries(root, Path::new(""), manifest, &mut entries)?;
+
+        Ok(Snapshot {
+            root: root.to_string_lossy().into_owned(),
+            entries,
+        })
+    }
+
+    /// Reads a snapshot from a file
+    pub fn load(path: &Path) -> Result<Snapshot> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read snapshot from {:?}", path))?;
+
+        serde_json::from_str(&contents)
+            .with_context(|| format!("Failed to parse snapshot {:?}", path))
+    }
+
+    /// Writes the snapshot to a file
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let contents = serde_json::to_string_pretty(self)
+            .with_context(|| format!("Failed to write snapshot to {:?}", path))?;
+
+        std::fs::write(path, contents + "\n")
+            .with_context(|| format!("Failed to write snapshot to {:?}", path))
+    }
+
+    /// Recreates the snapshot's folders and symlinks inside `root`, which needn't be
+    /// the directory the snapshot was taken of. Symlinks which point elsewhere are replaced,
+    /// but anything else already in the way is left alone.
+    ///
+    /// Restored symlinks are recorded in `root`'s manifest, so lnshot can update and clean them up
+    /// like any other link it made.
+    pub fn restore(
+        &self,
+        root: &Path,
+        operations: &mut Operations,
+        manifest: &mut Manifest,
+    ) -> Result<()> {
+        Event::info(
+            "restore",
+            format!(
+                "Restoring {} item(s) from snapshot of {:?} into {:?}",
+                self.entries.len(),
+                self.root,
+                root
+            ),
+        )
+        .path(root)
+        .log();
+
+        operations.create_dir_all(root)?;
+
+        for entry in self.entries.iter() {
+            let path = root.join(&entry.path);
+
+            match (&entry.kind, &entry.target) {
+                (EntryKind::Directory, _) => {
+                    if !path.is_dir() {
+                        Event::info("restore", format!("Creating folder {:?}", path))
+                            .path(&path)
+                            .log();
+                        operations.create_dir_all(&path)?;
+                    }
+                }
+                (EntryKind::Symlink, Some(target)) => {
+                    let target = PathBuf::from(target);
+
+                    if path.is_symlink() {
+                        if std::fs::read_link(&path).ok().as_ref() == Some(&target) {
+                            record_link(manifest, entry, &path, &target);
+                            continue;
+                        }
+
+                        if let Err(error) = operations.remove_symlink(&path) {
+                            Event::error(
+                                "restore",
+                                format!("Error unlinking {:?}: {}", path, error),
+                            )
+                            .path(&path)
+                            .log();
+                            continue;
+                        }
+                    } else if path.exists() {
+                        Event::warn(
+                            "restore",
+                            format!("Skipping {:?}, as something else is in the way", path),
+                        )
+                        .path(&path)
+                        .log();
+                        continue;
+                    }
+
+                    Event::info("restore", format!("Symlinking {:?} to {:?}", path, target))
+                        .path(&path)
+                        .path(&target)
+                        .log();
+                    match operations.symlink_dir(&target, &path) {
+                        Ok(_) => record_link(manifest, entry, &path, &target),
+                        Err(error) => Event::error(
+                            "restore",
+                            format!("Error symlinking {:?} to {:?}: {}", target, path, error),
+                        )
+                        .path(&path)
+                        .path(&target)
+                        .log(),
+                    }
+                }
+                (EntryKind::Symlink, None) => {
+                    Event::warn(
+                        "restore",
+                        format!("Skipping {:?}, as its target wasn't recorded", path),
+                    )
+                    .path(&path)
+                    .log();
+                }
+            }
+        }
+
+        manifest.save(operations)
+    }
+}
+
+/// Records a restored symlink in the manifest. Snapshots taken before they recorded each link's app and user
+/// have them worked out from where the link points, if that's an app's screenshot folder.
+fn record_link(manifest: &mut Manifest, entry: &SnapshotEntry, path: &Path, target: &Path) {
+    let source = match path.parent() {
+        Some(parent) if target.is_relative() => {
+            let source = parent.join(target);
+            source.canonicalize().unwrap_or(source)
+        }
+        _ => target.to_path_buf(),
+    };
+
+    let app = match (entry.appid, &entry.user) {
+        (Some(appid), Some(user)) => Some((appid, user.clone())),
+        _ => app_of_screenshot_folder(&source),
+    };
+
+    match app {
+        Some((appid, user)) => manifest.record_link(path, &source, appid, &user),
+        None => Event::warn(
+            "restore",
+            format!(
+                "Not recording {:?} as lnshot's, as it doesn't point to an app's screenshot folder",
+                path
+            ),
+        )
+        .path(path)
+        .log(),
+    }
+}
+
+/// Works out the app ID and the SteamID64 of the user whose screenshot folder this is,
+/// from Steam's `userdata/<account ID>/760/remote/<app ID>/screenshots` layout
+fn app_of_screenshot_folder(source: &Path) -> Option<(u64, String)> {
+    let parts = source
+        .iter()
+        .map(|part| part.to_str())
+        .collect::<Option<Vec<_>>>()?;
+
+    let [.., account_id, "760", "remote", appid, "screenshots"] = parts.as_slice() else {
+        return None;
+    };
+
+    let steamid = SteamID::new(
+        account_id.parse().ok()?,
+        Instance::Desktop,
+        AccountType::Individual,
+        Universe::Public,
+    );
+
+    Some((appid.parse().ok()?, u64::from(steamid).to_string()))
+}
+
+/// Adds entries for everything inside `root.join(relative_dir)` to `entries`, recursively
+fn collect_entries(
+    root: &Path,
+    relative_dir: &Path,
+    manifest: &Manifest,
+    entries: &mut Vec<SnapshotEntry>,
+) -> Result<()> {
+    let mut dir_entries = root
+        .join(relative_dir)
+        .read_dir()?
+        .collect::<std::io::Result<Vec<_>>>()?;
+    dir_entries.sort_by_key(|dir_entry| dir_entry.file_name());
+
+    for dir_entry in dir_entries {
+        let relative_path = relative_dir.join(dir_entry.file_name());
+        let file_type = dir_entry.file_type()?;
+
+        if file_type.is_symlink() {
+            let link_record = manifest.link(&dir_entry.path());
+
+            entries.push(SnapshotEntry {
+                path: relative_path.to_string_lossy().into_owned(),
+                kind: EntryKind::Symlink,
+                target: Some(
+                    std::fs::read_link(dir_entry.path())?
+                        .to_string_lossy()
+                        .into_owned(),
+                ),
+                appid: link_record.map(|link_record| link_record.appid),
+                user: link_record.map(|link_record| link_record.user.clone()),
+            });
+        } else if file_type.is_dir() {
+            entries.push(SnapshotEntry {
+                path: relative_path.to_string_lossy().into_owned(),
+                kind: EntryKind::Directory,
+                target: None,
+                appid: None,
+                user: None,
+            });
+
+            collect_entries(root, &relative_path, manifest, entries)?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,56 @@
+//! Telling systemd how the daemon is doing, when it's run as a `Type=notify` service
+
+use std::time::Duration;
+
+/// Sends a status update such as `READY=1` to systemd, if it asked for them.
+/// Failures are only reported, as the daemon works the same either way.
+#[cfg(target_os = "linux")]
+pub fn notify(state: &str) {
+    use crate::log::Event;
+    use std::os::linux::net::SocketAddrExt;
+    use std::os::unix::net::{SocketAddr, UnixDatagram};
+
+    let Some(socket_path) = std::env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+
+    let socket_path = socket_path.to_string_lossy();
+
+    // Sockets starting with `@` are in the abstract namespace, rather than on disk
+    let address = match socket_path.strip_prefix('@') {
+        Some(name) => SocketAddr::from_abstract_name(name.as_bytes()),
+        None => SocketAddr::from_pathname(socket_path.as_ref()),
+    };
+
+    let result = UnixDatagram::unbound().and_then(|socket| {
+        socket.send_to_addr(state.as_bytes(), &address?)?;
+        Ok(())
+    });
+
+    if let Err(error) = result {
+        Event::error(
+            "systemd",
+            format!("Failed to notify systemd of {:?}: {}", state, error),
+        )
+        .log();
+    }
+}
+
+/// systemd only runs on Linux, so there's never anyone to notify elsewhere
+#[cfg(not(target_os = "linux"))]
+pub fn notify(_state: &str) {}
+
+/// How often systemd expects to hear `WATCHDOG=1` from this process, if at all.
+/// This is half the service's `WatchdogSec`, so a ping running a little late isn't mistaken for a hang.
+pub fn watchdog_interval() -> Option<Duration> {
+    let watchdog_usec = std::env::var("WATCHDOG_USEC").ok()?.parse::<u64>().ok()?;
+
+    // The watchdog may be meant for another process in the service
+    if let Ok(watchdog_pid) = std::env::var("WATCHDOG_PID") {
+        if watchdog_pid.parse::<u32>().ok()? != std::process::id() {
+            return None;
+        }
+    }
+
+    Some(Duration::from_micros(watchdog_usec) / 2).filter(|interval| !interval.is_zero())
+}
@@ -0,0 +1,177 @@
+//! Printing results as tables with aligned columns, colored when shown in a terminal
+
+use clap::ValueEnum;
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Whether tables are colored
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ColorChoice {
+    /// Only when writing to a terminal, and `NO_COLOR` isn't set
+    #[default]
+    Auto,
+
+    Always,
+
+    Never,
+}
+
+static COLOR: AtomicBool = AtomicBool::new(false);
+
+/// Chooses whether tables printed from now on are colored
+pub fn set_color(choice: ColorChoice) {
+    let color = match choice {
+        ColorChoice::Auto => {
+            std::io::stdout().is_terminal()
+                && std::env::var_os("NO_COLOR").is_none_or(|no_color| no_color.is_empty())
+        }
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+    };
+
+    COLOR.store(color, Ordering::Relaxed);
+}
+
+/// How a cell is colored, if tables are colored at all
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Color {
+    #[default]
+    Plain,
+
+    /// Everything is as it should be
+    Green,
+
+    /// Worth a look, but not a problem
+    Yellow,
+
+    /// Something is wrong
+    Red,
+
+    /// Left out on purpose, such as an excluded app
+    Dim,
+}
+
+impl Color {
+    fn escape_code(self) -> Option<&'static str> {
+        match self {
+            Color::Plain => None,
+            Color::Green => Some("32"),
+            Color::Yellow => Some("33"),
+            Color::Red => Some("31"),
+            Color::Dim => Some("2"),
+        }
+    }
+}
+
+/// A cell's text, and how it's colored
+#[derive(Debug, Clone, Default)]
+pub struct Cell {
+    text: String,
+    color: Color,
+}
+
+impl Cell {
+    pub fn colored(text: impl Into<String>, color: Color) -> Cell {
+        Cell {
+            text: text.into(),
+            color,
+        }
+    }
+}
+
+impl From<String> for Cell {
+    fn from(text: String) -> Cell {
+        Cell::colored(text, Color::Plain)
+    }
+}
+
+impl From<&str> for Cell {
+    fn from(text: &str) -> Cell {
+        Cell::colored(text, Color::Plain)
+    }
+}
+
+/// Rows of cells, printed with each column as wide as its widest cell
+#[derive(Debug)]
+pub struct Table {
+    headings: Vec<&'static str>,
+    rows: Vec<Vec<Cell>>,
+}
+
+impl Table {
+    pub fn new(headings: &[&'static str]) -> Table {
+        Table {
+            headings: headings.to_vec(),
+            rows: Vec::new(),
+        }
+    }
+
+    /// Adds a row, which should have a cell for each heading
+    pub fn row(&mut self, cells: Vec<Cell>) {
+        self.rows.push(cells);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    /// Prints the headings, then each row, to standard output
+    pub fn print(&self) {
+        let color = COLOR.load(Ordering::Relaxed);
+
+        let mut widths = self
+            .headings
+            .iter()
+            .map(|heading| heading.chars().count())
+            .collect::<Vec<_>>();
+        for row in &self.rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.text.chars().count());
+            }
+        }
+
+        let headings = self
+            .headings
+            .iter()
+            .map(|heading| Cell::colored(heading.to_uppercase(), Color::Plain))
+            .collect::<Vec<_>>();
+        println!("{}", format_row(&headings, &widths, color, true));
+
+        for row in &self.rows {
+            println!("{}", format_row(row, &widths, color, false));
+        }
+    }
+}
+
+/// Lays out a row's cells, padding all but the last so the next column lines up
+fn format_row(cells: &[Cell], widths: &[usize], color: bool, bold: bool) -> String {
+    let mut line = String::new();
+
+    for (index, (cell, width)) in cells.iter().zip(widths).enumerate() {
+        if index > 0 {
+            line.push_str("  ");
+        }
+
+        let escape_code = if bold {
+            Some("1")
+        } else {
+            cell.color.escape_code()
+        };
+
+        match escape_code.filter(|_| color && !cell.text.is_empty()) {
+            Some(escape_code) => {
+                line.push_str(&format!("\x1b[{}m{}\x1b[0m", escape_code, cell.text))
+            }
+            None => line.push_str(&cell.text),
+        }
+
+        if index + 1 < cells.len() {
+            let padding = width.saturating_sub(cell.text.chars().count());
+            line.push_str(&" ".repeat(padding));
+        }
+    }
+
+    // Trailing empty cells would otherwise leave padding at the end of the line
+    line.truncate(line.trim_end().len());
+    line
+}
@@ -0,0 +1,303 @@
+//! An icon in the notification area for controlling the daemon, for those who'd rather not use a terminal
+
+use crate::control::Request;
+use anyhow::Result;
+use std::path::PathBuf;
+
+/// Held by the running daemon, removing its icon once it stops
+#[cfg(windows)]
+#[derive(Debug)]
+pub struct TrayIcon {
+    window: usize,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+#[cfg(windows)]
+impl Drop for TrayIcon {
+    fn drop(&mut self) {
+        use windows_sys::Win32::UI::WindowsAndMessaging::{PostMessageW, WM_CLOSE};
+
+        unsafe { PostMessageW(self.window as _, WM_CLOSE, 0, 0) };
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Shows the icon, from a thread of its own. Choosing something from its menu sends the same
+/// commands as `lnshot ctl` to the handler.
+#[cfg(windows)]
+pub fn show<F>(screenshots_dir: PathBuf, handler: F) -> Result<TrayIcon>
+where
+    F: Fn(Request) + Send + 'static,
+{
+    use anyhow::Context;
+
+    let (window_channel, receive_channel) = std::sync::mpsc::channel();
+
+    let thread = std::thread::Builder::new()
+        .name("tray".to_string())
+        .spawn(move || {
+            // The icon's window has to be made on the thread which handles its messages
+            let window = match unsafe { windows::create_icon() } {
+                Ok(window) => window,
+                Err(error) => {
+                    let _ = window_channel.send(Err(error));
+                    return;
+                }
+            };
+
+            let _ = window_channel.send(Ok(window as usize));
+            unsafe { windows::run(window, &screenshots_dir, &handler) };
+        })
+        .with_context(|| "Failed to start the tray thread")?;
+
+    let window = receive_channel
+        .recv()
+        .with_context(|| "Failed to show the tray icon")??;
+
+    Ok(TrayIcon {
+        window,
+        thread: Some(thread),
+    })
+}
+
+/// Held by the running daemon, removing its icon once it stops
+#[cfg(not(windows))]
+#[derive(Debug)]
+pub struct TrayIcon;
+
+/// Tray icons are only supported on Windows so far
+#[cfg(not(windows))]
+pub fn show<F>(_screenshots_dir: PathBuf, _handler: F) -> Result<TrayIcon>
+where
+    F: Fn(Request) + Send + 'static,
+{
+    anyhow::bail!("The tray icon is only available on Windows so far");
+}
+
+#[cfg(windows)]
+mod windows {
+    use crate::control::{Command, Request};
+    use anyhow::Result;
+    use std::path::Path;
+    use std::time::Duration;
+    use windows_sys::Win32::Foundation::{HWND, LPARAM, LRESULT, POINT, WPARAM};
+    use windows_sys::Win32::System::LibraryLoader::GetModuleHandleW;
+    use windows_sys::Win32::UI::Shell::{
+        Shell_NotifyIconW, NIF_ICON, NIF_MESSAGE, NIF_TIP, NIM_ADD, NIM_DELETE, NOTIFYICONDATAW,
+    };
+    use windows_sys::Win32::UI::WindowsAndMessaging::{
+        AppendMenuW, CreatePopupMenu, CreateWindowExW, DefWindowProcW, DestroyMenu, DestroyWindow,
+        DispatchMessageW, GetCursorPos, GetMessageW, LoadIconW, PostMessageW, PostQuitMessage,
+        RegisterClassW, SetForegroundWindow, TrackPopupMenu, TranslateMessage, IDI_APPLICATION,
+        MF_GRAYED, MF_SEPARATOR, MF_STRING, MSG, TPM_NONOTIFY, TPM_RETURNCMD, TPM_RIGHTBUTTON,
+        WM_APP, WM_DESTROY, WM_LBUTTONUP, WM_RBUTTONUP, WNDCLASSW,
+    };
+
+    /// Sent by Windows when the icon is clicked
+    const ICON_MESSAGE: u32 = WM_APP + 1;
+
+    /// Sent to the thread's own message loop to show the menu, where the handler can be reached
+    const SHOW_MENU_MESSAGE: u32 = WM_APP + 2;
+
+    const PAUSE_ITEM: i32 = 1;
+    const RESYNC_ITEM: i32 = 2;
+    const OPEN_ITEM: i32 = 3;
+    const QUIT_ITEM: i32 = 4;
+
+    /// How long to wait for the daemon to answer, which may be busy linking everything
+    const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+    fn wide(text: &str) -> Vec<u16> {
+        text.encode_utf16().chain(std::iter::once(0)).collect()
+    }
+
+    fn icon_data(window: HWND) -> NOTIFYICONDATAW {
+        let mut icon_data: NOTIFYICONDATAW = unsafe { std::mem::zeroed() };
+        icon_data.cbSize = std::mem::size_of::<NOTIFYICONDATAW>() as u32;
+        icon_data.hWnd = window;
+        icon_data.uID = 1;
+        icon_data
+    }
+
+    /// Sends a command to the daemon, returning its answer if it arrives in time
+    fn ask<F: Fn(Request)>(handler: &F, command: Command) -> Option<String> {
+        let (request, receive_channel) = Request::new(command);
+        handler(request);
+        receive_channel.recv_timeout(REPLY_TIMEOUT).ok()
+    }
+
+    unsafe extern "system" fn window_procedure(
+        window: HWND,
+        message: u32,
+        wparam: WPARAM,
+        lparam: LPARAM,
+    ) -> LRESULT {
+        match message {
+            ICON_MESSAGE => {
+                let event = (lparam & 0xffff) as u32;
+                if event == WM_LBUTTONUP || event == WM_RBUTTONUP {
+                    PostMessageW(window, SHOW_MENU_MESSAGE, 0, 0);
+                }
+                0
+            }
+            WM_DESTROY => {
+                PostQuitMessage(0);
+                0
+            }
+            _ => DefWindowProcW(window, message, wparam, lparam),
+        }
+    }
+
+    /// Makes a hidden window to receive the icon's messages, and adds the icon
+    pub unsafe fn create_icon() -> Result<HWND> {
+        let instance = GetModuleHandleW(std::ptr::null());
+        let class_name = wide("lnshot-tray");
+
+        let mut class: WNDCLASSW = std::mem::zeroed();
+        class.lpfnWndProc = Some(window_procedure);
+        class.hInstance = instance;
+        class.lpszClassName = class_name.as_ptr();
+
+        if RegisterClassW(&class) == 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+
+        let window = CreateWindowExW(
+            0,
+            class_name.as_ptr(),
+            class_name.as_ptr(),
+            0,
+            0,
+            0,
+            0,
+            0,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            instance,
+            std::ptr::null(),
+        );
+
+        if window.is_null() {
+            return Err(std::io::Error::last_os_error().into());
+        }
+
+        let mut icon_data = icon_data(window);
+        icon_data.uFlags = NIF_ICON | NIF_MESSAGE | NIF_TIP;
+        icon_data.uCallbackMessage = ICON_MESSAGE;
+        icon_data.hIcon = LoadIconW(std::ptr::null_mut(), IDI_APPLICATION);
+
+        for (tip_character, character) in icon_data.szTip.iter_mut().zip(wide("lnshot")) {
+            *tip_character = character;
+        }
+
+        if Shell_NotifyIconW(NIM_ADD, &icon_data) == 0 {
+            DestroyWindow(window);
+            anyhow::bail!("Windows refused to add the icon");
+        }
+
+        Ok(window)
+    }
+
+    /// Shows the menu until something is chosen from it, returning which item was chosen
+    unsafe fn show_menu(window: HWND, status: &str, paused: bool) -> i32 {
+        let menu = CreatePopupMenu();
+
+        // The daemon's status stands in for recent activity, as it lists what was linked last
+        for line in status.lines() {
+            let text = wide(&line.replace('&', "&&"));
+            AppendMenuW(menu, MF_STRING | MF_GRAYED, 0, text.as_ptr());
+        }
+
+        let items = [
+            (PAUSE_ITEM, if paused { "Resume" } else { "Pause" }),
+            (RESYNC_ITEM, "Link everything again"),
+            (OPEN_ITEM, "Open screenshots folder"),
+        ];
+
+        AppendMenuW(menu, MF_SEPARATOR, 0, std::ptr::null());
+        for (item, label) in items {
+            let text = wide(label);
+            AppendMenuW(menu, MF_STRING, item as usize, text.as_ptr());
+        }
+
+        let quit_text = wide("Quit");
+        AppendMenuW(menu, MF_SEPARATOR, 0, std::ptr::null());
+        AppendMenuW(menu, MF_STRING, QUIT_ITEM as usize, quit_text.as_ptr());
+
+        let mut cursor = POINT { x: 0, y: 0 };
+        GetCursorPos(&mut cursor);
+
+        // Without this, the menu stays open when clicking anywhere else
+        SetForegroundWindow(window);
+
+        let chosen = TrackPopupMenu(
+            menu,
+            TPM_RETURNCMD | TPM_NONOTIFY | TPM_RIGHTBUTTON,
+            cursor.x,
+            cursor.y,
+            0,
+            window,
+            std::ptr::null(),
+        );
+
+        DestroyMenu(menu);
+        chosen
+    }
+
+    /// Handles the window's messages until it's closed, then removes the icon
+    pub unsafe fn run<F: Fn(Request)>(window: HWND, screenshots_dir: &Path, handler: &F) {
+        let mut paused = false;
+        let mut message: MSG = std::mem::zeroed();
+
+        while GetMessageW(&mut message, std::ptr::null_mut(), 0, 0) > 0 {
+            if message.message != SHOW_MENU_MESSAGE {
+                TranslateMessage(&message);
+                DispatchMessageW(&message);
+                continue;
+            }
+
+            let status = ask(handler, Command::Status)
+                .unwrap_or_else(|| "The daemon is busy; try again shortly".to_string());
+
+            match show_menu(window, &status, paused) {
+                PAUSE_ITEM => {
+                    let command = if paused {
+                        Command::Resume
+                    } else {
+                        Command::Pause
+                    };
+
+                    if ask(handler, command).is_some() {
+                        paused = !paused;
+                    }
+                }
+                RESYNC_ITEM => {
+                    ask(handler, Command::Resync);
+                }
+                OPEN_ITEM => {
+                    if let Err(error) = std::process::Command::new("explorer")
+                        .arg(screenshots_dir)
+                        .spawn()
+                    {
+                        crate::log::Event::error(
+                            "tray",
+                            format!("Failed to open {:?}: {}", screenshots_dir, error),
+                        )
+                        .path(screenshots_dir)
+                        .log();
+                    }
+                }
+                // The daemon closes the window as it stops
+                QUIT_ITEM => {
+                    ask(handler, Command::Stop);
+                }
+                _ => {}
+            }
+        }
+
+        Shell_NotifyIconW(NIM_DELETE, &icon_data(window));
+    }
+}
@@ -0,0 +1,166 @@
+//! Choosing which Steam users to process, by any of the ways a Steam account can be identified
+
+use crate::locator::SteamUser;
+use crate::log::Event;
+use anyhow::{Context, Result};
+use reqwest::blocking::{Client, Response};
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+use steamid_ng::SteamID;
+
+/// Longest to wait for the Steam Community to answer
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Identifies a Steam user, as given on the command line or in the config file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UserSelector {
+    /// A full Steam ID, given as a SteamID64, a SteamID2 like `STEAM_0:1:4`, or a SteamID3 like `[U:1:9]`
+    SteamId(u64),
+
+    /// An account ID, which names the user's folder in Steam's `userdata` directory
+    AccountId(u64),
+
+    /// A custom Steam Community profile URL, or just the name at the end of one
+    Vanity(String),
+}
+
+impl FromStr for UserSelector {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        let value = value.trim();
+
+        if value.starts_with("STEAM_") {
+            let steamid = SteamID::from_steam2(value)
+                .map_err(|_| anyhow::anyhow!("{:?} isn't a valid SteamID2", value))?;
+            return Ok(UserSelector::SteamId(u64::from(steamid)));
+        }
+
+        if value.starts_with('[') {
+            let steamid = SteamID::from_steam3(value)
+                .map_err(|_| anyhow::anyhow!("{:?} isn't a valid SteamID3", value))?;
+            return Ok(UserSelector::SteamId(u64::from(steamid)));
+        }
+
+        if let Ok(id) = value.parse::<u64>() {
+            // Account IDs are 32 bits, while SteamID64s always have their universe in the upper bits
+            return Ok(if id <= u64::from(u32::MAX) {
+                UserSelector::AccountId(id)
+            } else {
+                UserSelector::SteamId(id)
+            });
+        }
+
+        let path = value
+            .trim_start_matches("https://")
+            .trim_start_matches("http://")
+            .trim_start_matches("www.")
+            .trim_end_matches('/');
+
+        if let Some(id_str) = path.strip_prefix("steamcommunity.com/profiles/") {
+            return Ok(UserSelector::SteamId(id_str.parse().with_context(
+                || format!("{:?} isn't a valid Steam Community profile URL", value),
+            )?));
+        }
+
+        let vanity_name = path.strip_prefix("steamcommunity.com/id/").unwrap_or(path);
+
+        if vanity_name.is_empty() || vanity_name.contains('/') {
+            anyhow::bail!(
+                "{:?} isn't a Steam ID, account ID, or Steam Community profile URL",
+                value
+            );
+        }
+
+        Ok(UserSelector::Vanity(vanity_name.to_string()))
+    }
+}
+
+impl fmt::Display for UserSelector {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UserSelector::SteamId(steamid) => write!(formatter, "{}", steamid),
+            UserSelector::AccountId(account_id) => write!(formatter, "account ID {}", account_id),
+            UserSelector::Vanity(vanity_name) => write!(formatter, "{:?}", vanity_name),
+        }
+    }
+}
+
+impl UserSelector {
+    /// Looks up the SteamID64 behind a custom profile URL from the Steam Community.
+    /// Other kinds of selector are returned as they are.
+    pub fn resolve(self) -> Result<UserSelector> {
+        let UserSelector::Vanity(vanity_name) = self else {
+            return Ok(self);
+        };
+
+        Event::info(
+            "users",
+            format!(
+                "Looking up the Steam ID for {:?} from the Steam Community",
+                vanity_name
+            ),
+        )
+        .log();
+
+        Ok(UserSelector::SteamId(
+            fetch_vanity_steamid(&vanity_name).with_context(|| {
+                format!(
+                    "Failed to look up the Steam ID for profile {:?}",
+                    vanity_name
+                )
+            })?,
+        ))
+    }
+
+    /// Checks whether this selects the given user.
+    /// Custom profile URLs never match until they've been resolved.
+    pub fn matches(&self, user: &SteamUser) -> bool {
+        match self {
+            UserSelector::SteamId(steamid) => u64::from(user.steamid) == *steamid,
+            UserSelector::AccountId(account_id) => user.account_id() == *account_id,
+            UserSelector::Vanity(_) => false,
+        }
+    }
+
+    /// Checks whether this selects the user with the given account ID
+    pub fn matches_account_id(&self, account_id: u64) -> bool {
+        match self {
+            UserSelector::SteamId(steamid) => {
+                u64::from(SteamID::from(*steamid).account_id()) == account_id
+            }
+            UserSelector::AccountId(selected_account_id) => *selected_account_id == account_id,
+            UserSelector::Vanity(_) => false,
+        }
+    }
+}
+
+/// Asks the Steam Community for the SteamID64 of a custom profile URL.
+/// The profile's XML form is used, as the Web API needs a key for this.
+fn fetch_vanity_steamid(vanity_name: &str) -> Result<u64> {
+    let response = Client::builder()
+        .timeout(TIMEOUT)
+        .build()?
+        .get(format!(
+            "https://steamcommunity.com/id/{}/?xml=1",
+            vanity_name
+        ))
+        .send()
+        .and_then(Response::error_for_status)
+        .and_then(Response::text)
+        .with_context(|| "Failed to reach the Steam Community")?;
+
+    let steamid_str = response
+        .split_once("<steamID64>")
+        .and_then(|(_, rest)| rest.split_once("</steamID64>"))
+        .map(|(steamid_str, _)| steamid_str.trim())
+        .with_context(|| "No Steam Community profile has this URL")?;
+
+    steamid_str.parse().with_context(|| {
+        format!(
+            "The Steam Community returned an invalid Steam ID {:?}",
+            steamid_str
+        )
+    })
+}
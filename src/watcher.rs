@@ -0,0 +1,152 @@
+//! Watching Steam's folders for changes, natively where the file system allows it, or by polling where it doesn't
+
+use anyhow::{Context, Result};
+use notify_debouncer_mini::notify::{
+    self, PollWatcher, RecommendedWatcher, RecursiveMode, Watcher,
+};
+use notify_debouncer_mini::{
+    new_debouncer, new_debouncer_opt, Config, DebounceEventHandler, Debouncer,
+};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// File systems which don't report changes made from other computers, so have to be polled instead
+const NETWORK_FILE_SYSTEMS: [&str; 12] = [
+    "nfs",
+    "nfs4",
+    "cifs",
+    "smb3",
+    "smbfs",
+    "9p",
+    "afs",
+    "ceph",
+    "glusterfs",
+    "davfs",
+    "fuse.sshfs",
+    "fuse.rclone",
+];
+
+/// How to let Linux watch more folders, for when watching every game's folders uses up its limit
+pub const WATCH_LIMIT_FIX: &str = "Raise the inotify watch limit, for example with `sudo sysctl fs.inotify.max_user_watches=524288`, \
+    and add `fs.inotify.max_user_watches=524288` to a file in /etc/sysctl.d to keep it after restarting";
+
+/// Watches for changes, reporting them in debounced batches
+pub enum FileWatcher {
+    /// The operating system reports changes as they happen
+    Native(Debouncer<RecommendedWatcher>),
+
+    /// Folders are scanned for changes at regular intervals
+    Polling(Debouncer<PollWatcher>),
+}
+
+impl FileWatcher {
+    /// Watches for changes using the operating system's own notifications
+    pub fn native<F: DebounceEventHandler>(
+        timeout: Duration,
+        event_handler: F,
+    ) -> Result<FileWatcher> {
+        Ok(FileWatcher::Native(new_debouncer(timeout, event_handler)?))
+    }
+
+    /// Watches for changes by scanning every `poll_interval`, which works on any file system
+    pub fn polling<F: DebounceEventHandler>(
+        timeout: Duration,
+        poll_interval: Duration,
+        event_handler: F,
+    ) -> Result<FileWatcher> {
+        let config = Config::default()
+            .with_timeout(timeout)
+            .with_notify_config(notify::Config::default().with_poll_interval(poll_interval));
+
+        Ok(FileWatcher::Polling(new_debouncer_opt::<_, PollWatcher>(
+            config,
+            event_handler,
+        )?))
+    }
+
+    /// The underlying watcher, for choosing what to watch
+    pub fn watcher(&mut self) -> &mut dyn Watcher {
+        match self {
+            FileWatcher::Native(debouncer) => debouncer.watcher(),
+            FileWatcher::Polling(debouncer) => debouncer.watcher(),
+        }
+    }
+}
+
+/// Checks whether watching failed because the system's limit on watched folders was reached
+pub fn is_watch_limit_error(error: &notify::Error) -> bool {
+    matches!(error.kind, notify::ErrorKind::MaxFilesWatch)
+}
+
+/// Watches each user's screenshot folder inside `userdata` which isn't watched yet, returning how many were added.
+/// This needs far fewer watches than all of `userdata`, but users' folders made later aren't noticed until this is run again.
+pub fn watch_remote_dirs(
+    watcher: &mut dyn Watcher,
+    user_data_dir: &Path,
+    watched_dirs: &mut BTreeSet<PathBuf>,
+) -> Result<usize> {
+    let mut added = 0;
+
+    let user_dirs = user_data_dir
+        .read_dir()
+        .with_context(|| format!("Failed to read {:?}", user_data_dir))?;
+
+    for user_dir in user_dirs.flatten() {
+        let remote_dir = user_dir.path().join("760").join("remote");
+
+        if !remote_dir.is_dir() || watched_dirs.contains(&remote_dir) {
+            continue;
+        }
+
+        if let Err(error) = watcher.watch(&remote_dir, RecursiveMode::Recursive) {
+            let limit_reached = is_watch_limit_error(&error);
+            let error =
+                anyhow::Error::from(error).context(format!("Failed to watch {:?}", remote_dir));
+
+            return Err(if limit_reached {
+                error.context(WATCH_LIMIT_FIX)
+            } else {
+                error
+            });
+        }
+
+        watched_dirs.insert(remote_dir);
+        added += 1;
+    }
+
+    Ok(added)
+}
+
+/// Finds the type of file system a folder is on, if it's one which has to be polled for changes.
+/// File system types are read from `/proc`, so nothing is detected on systems other than Linux.
+pub fn network_file_system(dir: &Path) -> Option<String> {
+    let mounts = std::fs::read_to_string("/proc/self/mounts").ok()?;
+
+    // The folder is on whichever mount point is the longest prefix of its path
+    let (_mount_point, file_system) = mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let _device = fields.next()?;
+            let mount_point = PathBuf::from(unescape_mount_field(fields.next()?));
+            let file_system = fields.next()?;
+
+            Some((mount_point, file_system))
+        })
+        .filter(|(mount_point, _file_system)| dir.starts_with(mount_point))
+        .max_by_key(|(mount_point, _file_system)| mount_point.as_os_str().len())?;
+
+    NETWORK_FILE_SYSTEMS
+        .contains(&file_system)
+        .then(|| file_system.to_string())
+}
+
+/// Undoes the octal escapes `/proc/self/mounts` uses for spaces and other separators in paths
+fn unescape_mount_field(field: &str) -> String {
+    field
+        .replace("\\040", " ")
+        .replace("\\011", "\t")
+        .replace("\\012", "\n")
+        .replace("\\134", "\\")
+}
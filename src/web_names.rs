@@ -0,0 +1,164 @@
+//! Names for apps which aren't installed, looked up from the Steam store and cached on disk
+
+use crate::log::Event;
+use anyhow::{Context, Result};
+use directories::ProjectDirs;
+use reqwest::blocking::{Client, Response};
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Steam store endpoint which describes an app
+const STORE_API_URL: &str = "https://store.steampowered.com/api/appdetails";
+
+/// Longest to wait for the Steam store to answer each lookup
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// App names looked up from the Steam store, kept on disk so each app is only looked up once
+pub struct WebNameCache {
+    path: PathBuf,
+
+    /// App names by app ID, with `None` for apps the store doesn't know about
+    names: BTreeMap<u64, Option<String>>,
+
+    changed: bool,
+}
+
+impl WebNameCache {
+    /// Where the cache is kept, such as `~/.cache/lnshot/app_names.json` on Linux
+    pub fn default_path() -> Option<PathBuf> {
+        ProjectDirs::from_path(PathBuf::from("lnshot"))
+            .map(|project_dirs| project_dirs.cache_dir().join("app_names.json"))
+    }
+
+    /// Reads the cache from the given path. A missing or unreadable cache is treated as empty.
+    pub fn load(path: &Path) -> WebNameCache {
+        let names = match std::fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|_| {
+                Event::warn(
+                    "web_names",
+                    format!("Ignoring unreadable app name cache {:?}", path),
+                )
+                .path(path)
+                .log();
+
+                BTreeMap::new()
+            }),
+            Err(_) => BTreeMap::new(),
+        };
+
+        WebNameCache {
+            path: path.to_path_buf(),
+            names,
+            changed: false,
+        }
+    }
+
+    /// Finds names for the given app IDs, looking up any which aren't cached yet from the Steam store.
+    /// Apps without names are left out.
+    ///
+    /// If the store can't be reached, the remaining apps aren't looked up until the next run.
+    pub fn lookup(&mut self, appids: impl IntoIterator<Item = u64>) -> HashMap<u64, String> {
+        let mut found_names = HashMap::new();
+        let mut store_reachable = true;
+
+        for appid in appids {
+            let name = match self.names.entry(appid) {
+                Entry::Occupied(entry) => entry.get().clone(),
+                Entry::Vacant(entry) => {
+                    if !store_reachable {
+                        continue;
+                    }
+
+                    Event::info("web_names", "Looking up app name from the Steam store")
+                        .appid(appid)
+                        .log();
+
+                    match fetch_name(appid) {
+                        Ok(name) => {
+                            self.changed = true;
+                            entry.insert(name).clone()
+                        }
+                        Err(error) => {
+                            Event::warn(
+                                "web_names",
+                                format!(
+                                    "Unable to look up app names from the Steam store: {:#}",
+                                    error
+                                ),
+                            )
+                            .log();
+                            store_reachable = false;
+                            continue;
+                        }
+                    }
+                }
+            };
+
+            if let Some(name) = name {
+                found_names.insert(appid, name);
+            }
+        }
+
+        found_names
+    }
+
+    /// Finds names for the given app IDs from the cache alone, without looking anything up
+    pub fn cached(&self, appids: impl IntoIterator<Item = u64>) -> HashMap<u64, String> {
+        appids
+            .into_iter()
+            .filter_map(|appid| Some((appid, self.names.get(&appid)?.clone()?)))
+            .collect()
+    }
+
+    /// Writes the cache back to disk, if anything new was looked up
+    pub fn save(&mut self) -> Result<()> {
+        if !self.changed {
+            return Ok(());
+        }
+
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create cache folder {:?}", parent))?;
+        }
+
+        let contents = serde_json::to_string_pretty(&self.names)
+            .with_context(|| format!("Failed to write app name cache {:?}", self.path))?;
+
+        std::fs::write(&self.path, contents + "\n")
+            .with_context(|| format!("Failed to write app name cache {:?}", self.path))?;
+
+        self.changed = false;
+        Ok(())
+    }
+}
+
+/// Asks the Steam store for an app's name, returning `None` if the store doesn't know about it
+fn fetch_name(appid: u64) -> Result<Option<String>> {
+    let response = Client::builder()
+        .timeout(TIMEOUT)
+        .build()?
+        .get(STORE_API_URL)
+        .query(&[("appids", appid.to_string().as_str()), ("filters", "basic")])
+        .send()
+        .and_then(Response::error_for_status)
+        .with_context(|| "Failed to reach the Steam store")?
+        .json::<serde_json::Value>()
+        .with_context(|| "Failed to parse the Steam store's response")?;
+
+    let Some(app) = response.get(appid.to_string()) else {
+        return Ok(None);
+    };
+
+    if app.get("success").and_then(serde_json::Value::as_bool) != Some(true) {
+        return Ok(None);
+    }
+
+    Ok(app
+        .get("data")
+        .and_then(|data| data.get("name"))
+        .and_then(serde_json::Value::as_str)
+        .filter(|name| !name.trim().is_empty())
+        .map(str::to_string))
+}
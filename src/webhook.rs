@@ -0,0 +1,99 @@
+//! Telling a web service about new screenshots, by posting JSON to a URL of the user's choosing
+
+use crate::hooks::NewScreenshot;
+use crate::log::Event;
+use anyhow::{Context, Result};
+use reqwest::blocking::{Client, Response};
+use reqwest::header::CONTENT_TYPE;
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// How many times each screenshot is posted before giving up on it
+const ATTEMPTS: u32 = 5;
+
+/// Longest to wait for the server to answer each post
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long to wait after the first failure, doubling after each one after that
+const FIRST_RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// Posts new screenshots to a webhook from a thread of its own, so a slow or unreachable server
+/// doesn't hold up the daemon. Screenshots are posted in the order they were found.
+pub struct Webhook {
+    transmit_channel: mpsc::Sender<String>,
+}
+
+impl Webhook {
+    /// Starts posting to the URL
+    pub fn start(url: String) -> Result<Webhook> {
+        let (transmit_channel, receive_channel) = mpsc::channel::<String>();
+        let client = Client::builder()
+            .timeout(TIMEOUT)
+            .build()
+            .with_context(|| "Failed to set up the webhook's HTTP client")?;
+
+        std::thread::Builder::new()
+            .name("webhook".to_string())
+            .spawn(move || {
+                for payload in receive_channel {
+                    post_with_retries(&client, &url, &payload);
+                }
+            })
+            .with_context(|| "Failed to start the webhook thread")?;
+
+        Ok(Webhook { transmit_channel })
+    }
+
+    /// Queues a screenshot to be posted, described as in [`NewScreenshot::to_json`]
+    pub fn send(&self, screenshot: &NewScreenshot) {
+        let _ = self.transmit_channel.send(screenshot.to_json());
+    }
+}
+
+/// Posts a payload, waiting longer after each failure before trying again
+fn post_with_retries(client: &Client, url: &str, payload: &str) {
+    let mut retry_delay = FIRST_RETRY_DELAY;
+
+    for attempt in 1..=ATTEMPTS {
+        let error = match post(client, url, payload) {
+            Ok(()) => return,
+            Err(error) => error,
+        };
+
+        if attempt == ATTEMPTS {
+            Event::error(
+                "webhook",
+                format!(
+                    "Giving up on posting to the webhook after {} attempts: {:#}",
+                    ATTEMPTS, error
+                ),
+            )
+            .log();
+            return;
+        }
+
+        Event::warn(
+            "webhook",
+            format!(
+                "Failed to post to the webhook, so trying again in {} second(s): {:#}",
+                retry_delay.as_secs(),
+                error
+            ),
+        )
+        .log();
+
+        std::thread::sleep(retry_delay);
+        retry_delay *= 2;
+    }
+}
+
+fn post(client: &Client, url: &str, payload: &str) -> Result<()> {
+    client
+        .post(url)
+        .header(CONTENT_TYPE, "application/json")
+        .body(payload.to_string())
+        .send()
+        .and_then(Response::error_for_status)?;
+
+    Ok(())
+}